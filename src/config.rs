@@ -1,10 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize as _, Deserializer};
 use serde_derive::Deserialize;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 pub struct General {
     pub database_path: Option<String>,
+    pub jobs: Option<usize>,
 }
 
 impl Default for General {
@@ -14,23 +18,216 @@ impl Default for General {
 
         Self {
             database_path: Some(config_path.to_str().unwrap().to_string()),
+            jobs: None,
         }
     }
 }
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchPolicy {
+    Always,
+    Never,
+    Stale,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FetchRule {
+    pub pattern: String,
+    pub policy: FetchPolicy,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Fetch {
+    pub policy: FetchPolicy,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub stale_after: Duration,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,
+    pub rules: Vec<FetchRule>,
+}
+
+impl Default for Fetch {
+    fn default() -> Self {
+        Self {
+            policy: FetchPolicy::Always,
+            stale_after: Duration::from_secs(24 * 60 * 60),
+            timeout: Duration::from_secs(60),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Fetch {
+    /// Policy of the first rule whose pattern matches `path`, or the default policy.
+    pub fn policy_for(&self, path: &Path) -> FetchPolicy {
+        self.rules
+            .iter()
+            .find(|rule| {
+                glob::Pattern::new(&expand_tilde(&rule.pattern))
+                    .map(|pattern| pattern.matches_path(path))
+                    .unwrap_or(false)
+            })
+            .map(|rule| rule.policy)
+            .unwrap_or(self.policy)
+    }
+
+    pub fn should_fetch(&self, path: &Path, last_fetch: Option<DateTime<Utc>>) -> bool {
+        match self.policy_for(path) {
+            FetchPolicy::Always => true,
+            FetchPolicy::Never => false,
+            FetchPolicy::Stale => match last_fetch {
+                Some(last_fetch) => (Utc::now() - last_fetch)
+                    .to_std()
+                    .map(|elapsed| elapsed >= self.stale_after)
+                    .unwrap_or(false),
+                None => true,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScanRoot {
+    pub path: String,
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
-pub struct Config {
-    pub general: General,
+#[serde(default)]
+pub struct Scan {
+    pub roots: Vec<ScanRoot>,
+    pub max_depth: Option<usize>,
+    pub exclude: Vec<String>,
+    pub skip_hidden: bool,
+    /// Keep descending into repos to find repos nested inside them.
+    pub nested: bool,
+    /// Descend into symlinked directories; loops are detected either way.
+    pub follow_symlinks: bool,
+    /// Drop stored repos below the scanned roots that no longer exist.
+    pub prune: bool,
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            max_depth: None,
+            exclude: vec!["**/node_modules".to_string(), "**/target".to_string()],
+            skip_hidden: true,
+            nested: false,
+            follow_symlinks: false,
+            prune: false,
+        }
+    }
+}
+
+/// How merge commits contribute to line counts.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStats {
+    /// Record merges without any changes.
+    Exclude,
+    /// Diff against the first parent, crediting the whole merged branch to the merge.
+    /// Those changes are also counted on the branch's own commits, so they count twice.
+    FirstParent,
+    /// Diff against the automatic merge of both parents, counting only conflict resolutions
+    /// and other edits made in the merge itself.
+    AutoMerge,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct History {
+    /// Similarity (0-100) above which a delete and add pair counts as a rename or copy.
+    pub rename_threshold: u16,
+    /// Like `git -C`, copies are only found from files modified in the same commit.
+    pub detect_copies: bool,
+    pub merges: MergeStats,
+    /// Refs whose history is collected: `HEAD`, `local`, `remote` or ref name globs.
+    pub refs: Vec<String>,
+}
+
+pub const HISTORY_HEAD: &str = "HEAD";
+pub const HISTORY_LOCAL: &str = "local";
+pub const HISTORY_REMOTE: &str = "remote";
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            rename_threshold: 50,
+            detect_copies: true,
+            merges: MergeStats::Exclude,
+            refs: vec![HISTORY_HEAD.to_string()],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Report {
+    /// Share of a commit credited to each `Co-authored-by` co-author, 0 to ignore them.
+    pub co_author_weight: f64,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self {
+            co_author_weight: 1.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Snapshots {
+    /// Snapshots older than this are dropped, a year by default. `"forever"` keeps them all.
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub max_age: Option<Duration>,
+    /// Newest snapshots kept per repo, unlimited when unset.
+    pub max_count: Option<usize>,
 }
 
-impl Default for Config {
+impl Default for Snapshots {
     fn default() -> Self {
         Self {
-            general: General::default(),
+            max_age: Some(Duration::from_secs(365 * 24 * 60 * 60)),
+            max_count: None,
         }
     }
 }
 
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    pub general: General,
+    #[serde(default)]
+    pub fetch: Fetch,
+    #[serde(default)]
+    pub scan: Scan,
+    #[serde(default)]
+    pub history: History,
+    #[serde(default)]
+    pub report: Report,
+    #[serde(default)]
+    pub snapshots: Snapshots,
+}
+
+impl General {
+    /// Number of repos checked in parallel, defaulting to the number of CPUs.
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        })
+    }
+}
+
 impl Config {
     pub fn new(path: &Path) -> Result<Self, toml::de::Error> {
         let contents = fs::read_to_string(path).expect("Failed to read config file");
@@ -38,6 +235,203 @@ impl Config {
     }
 
     pub fn validate(&self) -> Result<(), String> {
+        if self.general.jobs == Some(0) {
+            return Err("general.jobs must be at least 1".to_string());
+        }
+        if self.history.rename_threshold > 100 {
+            return Err("history.rename_threshold must be between 0 and 100".to_string());
+        }
+        for pattern in &self.scan.exclude {
+            glob::Pattern::new(&expand_tilde(pattern))
+                .map_err(|e| format!("Invalid scan exclude pattern '{}': {}", pattern, e))?;
+        }
+        for root in &self.scan.roots {
+            for pattern in root.include.iter().chain(&root.exclude) {
+                glob::Pattern::new(&expand_tilde(pattern)).map_err(|e| {
+                    format!(
+                        "Invalid scan pattern '{}' for root '{}': {}",
+                        pattern, root.path, e
+                    )
+                })?;
+            }
+        }
+        if !(0.0..=1.0).contains(&self.report.co_author_weight) {
+            return Err("report.co_author_weight must be between 0 and 1".to_string());
+        }
+        for spec in &self.history.refs {
+            if ![HISTORY_HEAD, HISTORY_LOCAL, HISTORY_REMOTE].contains(&spec.as_str()) {
+                glob::Pattern::new(spec)
+                    .map_err(|e| format!("Invalid history ref pattern '{}': {}", spec, e))?;
+            }
+        }
+        if self.fetch.timeout.is_zero() {
+            return Err("fetch.timeout must be greater than 0".to_string());
+        }
+        for rule in &self.fetch.rules {
+            glob::Pattern::new(&expand_tilde(&rule.pattern))
+                .map_err(|e| format!("Invalid fetch rule pattern '{}': {}", rule.pattern, e))?;
+        }
         Ok(())
     }
 }
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_tilde(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => match dirs::home_dir() {
+            Some(home) => format!("{}{}", home.display(), rest),
+            None => path.to_string(),
+        },
+        _ => path.to_string(),
+    }
+}
+
+/// Parses durations such as `90s`, `30m`, `12h`, `2d` or `1w`; a bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}'", value))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit '{}', expected one of: s, m, h, d, w",
+                unit
+            ))
+        }
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Duration '{}' is too long", value))
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    if value.trim() == "forever" {
+        return Ok(None);
+    }
+    parse_duration(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 30m "), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(
+            parse_duration("2d"),
+            Ok(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_duration("1w"),
+            Ok(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        let too_long = format!("{}w", u64::MAX / 2);
+        for invalid in ["", "m", "-5s", "1.5h", "3y", "2 d", &too_long] {
+            assert!(
+                parse_duration(invalid).is_err(),
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_fetch() {
+        let now = Utc::now();
+        let mut fetch = Fetch {
+            policy: FetchPolicy::Stale,
+            stale_after: Duration::from_secs(24 * 60 * 60),
+            ..Fetch::default()
+        };
+        let path = Path::new("/repos/app");
+        assert!(fetch.should_fetch(path, None));
+        assert!(!fetch.should_fetch(path, Some(now - chrono::Duration::hours(1))));
+        assert!(fetch.should_fetch(path, Some(now - chrono::Duration::days(2))));
+        // A fetch time ahead of the clock is treated as fresh.
+        assert!(!fetch.should_fetch(path, Some(now + chrono::Duration::hours(1))));
+
+        fetch.policy = FetchPolicy::Always;
+        assert!(fetch.should_fetch(path, Some(now)));
+        fetch.policy = FetchPolicy::Never;
+        assert!(!fetch.should_fetch(path, None));
+    }
+
+    #[test]
+    fn test_snapshot_retention() {
+        let config: Config = toml::from_str("[general]").unwrap();
+        assert_eq!(
+            config.snapshots.max_age,
+            Some(Duration::from_secs(365 * 24 * 60 * 60))
+        );
+        let config: Config =
+            toml::from_str("[general]\n[snapshots]\nmax_age = \"forever\"\nmax_count = 10")
+                .unwrap();
+        assert_eq!(config.snapshots.max_age, None);
+        assert_eq!(config.snapshots.max_count, Some(10));
+        assert!(toml::from_str::<Config>("[general]\n[snapshots]\nmax_age = \"never\"").is_err());
+    }
+
+    #[test]
+    fn test_fetch_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [general]
+            [fetch]
+            policy = "always"
+            stale_after = "2h"
+            [[fetch.rules]]
+            pattern = "/mirrors/**"
+            policy = "never"
+            [[fetch.rules]]
+            pattern = "/mirrors/keep"
+            policy = "always"
+            "#,
+        )
+        .unwrap();
+        let fetch = &config.fetch;
+        assert_eq!(fetch.stale_after, Duration::from_secs(2 * 60 * 60));
+        // The first matching rule wins.
+        assert_eq!(
+            fetch.policy_for(Path::new("/mirrors/keep")),
+            FetchPolicy::Never
+        );
+        assert_eq!(
+            fetch.policy_for(Path::new("/repos/app")),
+            FetchPolicy::Always
+        );
+        assert!(!fetch.should_fetch(Path::new("/mirrors/linux"), None));
+        assert!(config.validate().is_ok());
+
+        let config: Config = toml::from_str("[general]\n[fetch]\ntimeout = \"0s\"").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
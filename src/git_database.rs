@@ -1,24 +1,101 @@
-use bincode;
+use bincode::Options;
+use chrono::{DateTime, Datelike, FixedOffset, Utc};
 use colored::*;
 use git2::Time;
 use log::debug;
 use semver::Version;
 use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
-use sled::Db;
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use sled::{Db, Tree};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokei::Languages;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GitRepoInfo {
     pub path: String,
-    pub status: String,
+    pub status: Vec<FileStatus>,
+    pub origin_url: String,
+    /// Oldest root commit, together with `origin_url` it identifies a repo across moves.
+    pub root_commit: Option<String>,
+    pub unpushed_commits: String,
+    pub remote_updates: String,
+    pub branches: Vec<BranchInfo>,
+    pub default_branch: Option<String>,
+    /// Checked out branch (`HEAD` when detached) and its commit.
+    pub head: Option<HistoryTip>,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub fetch_status: FetchStatus,
+    pub kind: RepoKind,
+    pub parent: Option<String>,
+    pub bare: Option<BareRepoInfo>,
+    pub app_version: Version,
+    pub commits: Option<Vec<GitCommit>>,
+    /// Refs the stored history was collected from, to pick up where it left off.
+    pub history_tips: Vec<HistoryTip>,
+    /// Most recent tag reachable from HEAD.
+    pub latest_tag: Option<HistoryTip>,
+    pub languages: Option<Languages>,
+}
+
+/// Schema 5 layout, before the root commit was recorded.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GitRepoInfoV5 {
+    pub path: String,
+    pub status: Vec<FileStatus>,
+    pub origin_url: String,
+    pub unpushed_commits: String,
+    pub remote_updates: String,
+    pub branches: Vec<BranchInfo>,
+    pub default_branch: Option<String>,
+    pub head: Option<HistoryTip>,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub fetch_status: FetchStatus,
+    pub kind: RepoKind,
+    pub parent: Option<String>,
+    pub bare: Option<BareRepoInfo>,
+    pub app_version: Version,
+    pub commits: Option<Vec<GitCommit>>,
+    pub history_tips: Vec<HistoryTip>,
+    pub latest_tag: Option<HistoryTip>,
+    pub languages: Option<Languages>,
+}
+
+/// Schema 4 layout, before the checked out head was recorded.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GitRepoInfoV4 {
+    pub path: String,
+    pub status: Vec<FileStatus>,
     pub origin_url: String,
     pub unpushed_commits: String,
     pub remote_updates: String,
+    pub branches: Vec<BranchInfo>,
+    pub default_branch: Option<String>,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub fetch_status: FetchStatus,
+    pub kind: RepoKind,
+    pub parent: Option<String>,
+    pub bare: Option<BareRepoInfo>,
     pub app_version: Version,
     pub commits: Option<Vec<GitCommit>>,
+    pub history_tips: Vec<HistoryTip>,
+    pub latest_tag: Option<HistoryTip>,
+    pub languages: Option<Languages>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GitRepoInfoV061 {
+    pub path: String,
+    pub status: String,
+    pub origin_url: String,
+    pub unpushed_commits: String,
+    pub remote_updates: String,
+    pub app_version: Version,
+    pub commits: Option<Vec<GitCommitV061>>,
     pub languages: Option<Languages>,
 }
 
@@ -30,7 +107,7 @@ pub struct GitRepoInfoV051 {
     pub unpushed_commits: String,
     pub remote_updates: String,
     pub app_version: Version,
-    pub commits: Option<Vec<GitCommit>>,
+    pub commits: Option<Vec<GitCommitV061>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,18 +120,182 @@ pub struct GitRepoInfoV030 {
     pub app_version: Version,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Unmodified,
+    Modified,
+    Added,
+    Deleted,
+    Renamed,
+    TypeChanged,
+    Untracked,
+    Ignored,
+    Conflicted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileStatus {
+    pub path: String,
+    pub orig_path: Option<String>,
+    pub index: FileState,
+    pub worktree: FileState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatusSummary {
+    pub staged: usize,
+    pub modified: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    pub ignored: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusCategory {
+    Staged,
+    Modified,
+    Untracked,
+    Conflicted,
+    Ignored,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BranchInfo {
+    pub name: String,
+    pub upstream: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FetchStatus {
+    Skipped,
+    Fetched,
+    NoRemote,
+    Failed(String),
+    TimedOut,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepoKind {
+    Standalone,
+    Nested,
+    Submodule,
+    Worktree,
+}
+
+/// Details recorded for bare repos in place of the work tree status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BareRepoInfo {
+    pub tags: usize,
+    pub last_commit: Option<DateTime<Utc>>,
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GitCommit {
     pub hash: String,
+    pub author_name: String,
     pub author_email: String,
     pub time: SerializableTime,
+    pub committer_name: String,
+    pub committer_email: String,
+    pub commit_time: SerializableTime,
+    pub parents: Vec<String>,
+    pub message: String,
+    pub file_changes: usize,
+    pub insertions: usize,
+    pub deletion: usize,
+    pub files: Vec<FileChange>,
+    /// Walked refs the commit is reachable from.
+    pub branches: Vec<String>,
+    /// Parsed from `message`, `None` when it does not follow Conventional Commits.
+    pub conventional: Option<ConventionalCommit>,
+    pub trailers: Vec<Trailer>,
+    /// From `Co-authored-by` trailers.
+    pub co_authors: Vec<CoAuthor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CoAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+/// Commits and lines credited to one author email.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthorStats {
+    pub name: String,
+    pub email: String,
+    pub commits: f64,
+    pub insertions: f64,
+    pub deletions: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConventionalCommit {
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub subject: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryTip {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+    TypeChanged,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    /// Source path of a rename or copy.
+    pub old_path: Option<String>,
+    pub kind: ChangeKind,
+    /// Binary changes carry no line counts.
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Number of commits and lines changed for one path across the stored history.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileChurn {
+    pub path: String,
+    pub commits: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Commit layout up to 0.6.1: author email only, and the time stored without its offset.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GitCommitV061 {
+    pub hash: String,
+    pub author_email: String,
+    pub time: i64,
     pub message: String,
     pub file_changes: usize,
     pub insertions: usize,
     pub deletion: usize,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SerializableTime(pub Time);
 
 #[derive(Debug)]
@@ -90,7 +331,7 @@ impl serde::Serialize for SerializableTime {
     where
         S: Serializer,
     {
-        serializer.serialize_i64(self.0.seconds())
+        serde::Serialize::serialize(&(self.0.seconds(), self.0.offset_minutes()), serializer)
     }
 }
 
@@ -99,51 +340,547 @@ impl<'de> serde::Deserialize<'de> for SerializableTime {
     where
         D: Deserializer<'de>,
     {
-        let seconds = i64::deserialize(deserializer)?;
-        Ok(SerializableTime(Time::new(seconds, 0)))
+        let (seconds, offset_minutes) =
+            <(i64, i32) as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(SerializableTime(Time::new(seconds, offset_minutes)))
+    }
+}
+
+impl SerializableTime {
+    /// The time in the committer's own timezone.
+    pub fn to_datetime(self) -> Option<DateTime<FixedOffset>> {
+        let offset = FixedOffset::east_opt(self.0.offset_minutes() * 60)?;
+        DateTime::from_timestamp(self.0.seconds(), 0).map(|time| time.with_timezone(&offset))
     }
 }
 
 impl GitCommit {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         hash: String,
+        author_name: String,
         author_email: String,
         time: SerializableTime,
+        committer_name: String,
+        committer_email: String,
+        commit_time: SerializableTime,
+        parents: Vec<String>,
         message: String,
         file_changes: usize,
         insertions: usize,
         deletion: usize,
+        files: Vec<FileChange>,
+        branches: Vec<String>,
     ) -> Self {
+        let trailers = parse_trailers(&message);
+        let conventional = ConventionalCommit::parse(&message, &trailers);
+        let co_authors = trailers
+            .iter()
+            .filter(|trailer| trailer.key.eq_ignore_ascii_case("Co-authored-by"))
+            .filter_map(|trailer| CoAuthor::parse(&trailer.value))
+            .collect();
         Self {
             hash,
+            author_name,
             author_email,
             time,
+            committer_name,
+            committer_email,
+            commit_time,
+            parents,
             message,
             file_changes,
             insertions,
             deletion,
+            files,
+            branches,
+            conventional,
+            trailers,
+            co_authors,
+        }
+    }
+
+    pub fn is_merge(&self) -> bool {
+        self.parents.len() > 1
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        self.conventional
+            .as_ref()
+            .map(|conventional| conventional.breaking)
+            .unwrap_or(false)
+    }
+}
+
+impl CoAuthor {
+    /// Parses `Name <email>`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (name, email) = value.trim().strip_suffix('>')?.rsplit_once('<')?;
+        let email = email.trim();
+        if email.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.trim().to_string(),
+            email: email.to_string(),
+        })
+    }
+}
+
+impl ConventionalCommit {
+    /// Parses a `type(scope)!: subject` header; breaking changes may also be announced by a
+    /// `BREAKING CHANGE` trailer.
+    pub fn parse(message: &str, trailers: &[Trailer]) -> Option<Self> {
+        let header = message.lines().next()?;
+        let (prefix, subject) = header.split_once(':')?;
+        let subject = subject.strip_prefix(' ')?.trim();
+        let (prefix, bang) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+        let (kind, scope) = match prefix.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?)),
+            None => (prefix, None),
+        };
+        if kind.is_empty()
+            || subject.is_empty()
+            || !kind.chars().all(|c| c.is_ascii_alphanumeric())
+            || scope.is_some_and(|scope| scope.is_empty() || scope.contains(['(', ')']))
+        {
+            return None;
+        }
+        let breaking = bang
+            || trailers.iter().any(|trailer| {
+                trailer.key == "BREAKING CHANGE" || trailer.key == "BREAKING-CHANGE"
+            });
+        Some(Self {
+            kind: kind.to_lowercase(),
+            scope: scope.map(|scope| scope.to_string()),
+            breaking,
+            subject: subject.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ConventionalCommit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(ref scope) = self.scope {
+            write!(f, "({})", scope)?;
+        }
+        if self.breaking {
+            write!(f, "!")?;
+        }
+        write!(f, ": {}", self.subject)
+    }
+}
+
+/// Trailers from the last paragraph of a commit message, if every line of it is one.
+/// Indented lines continue the previous trailer's value.
+pub fn parse_trailers(message: &str) -> Vec<Trailer> {
+    let paragraphs: Vec<&str> = message
+        .trim()
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .collect();
+    // A lone paragraph is the subject, never trailers.
+    let paragraph = match paragraphs.as_slice() {
+        [_, .., last] => last,
+        _ => return Vec::new(),
+    };
+    let mut trailers: Vec<Trailer> = Vec::new();
+    for line in paragraph.lines() {
+        if line.starts_with([' ', '\t']) {
+            match trailers.last_mut() {
+                Some(trailer) => {
+                    trailer.value.push(' ');
+                    trailer.value.push_str(line.trim());
+                    continue;
+                }
+                None => return Vec::new(),
+            }
         }
+        let Some((key, value)) = line.split_once(':') else {
+            return Vec::new();
+        };
+        let is_token =
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_token && key != "BREAKING CHANGE" {
+            return Vec::new();
+        }
+        trailers.push(Trailer {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+        });
+    }
+    trailers
+}
+
+impl From<GitCommitV061> for GitCommit {
+    fn from(commit: GitCommitV061) -> Self {
+        // Neither names, committer nor parents were recorded, and the offset was lost.
+        let time = SerializableTime(Time::new(commit.time, 0));
+        Self::new(
+            commit.hash,
+            String::new(),
+            commit.author_email.clone(),
+            time,
+            String::new(),
+            commit.author_email,
+            time,
+            Vec::new(),
+            commit.message,
+            commit.file_changes,
+            commit.insertions,
+            commit.deletion,
+            Vec::new(),
+            Vec::new(),
+        )
     }
 }
 
+fn migrate_commits(commits: Option<Vec<GitCommitV061>>) -> Option<Vec<GitCommit>> {
+    commits.map(|commits| commits.into_iter().map(GitCommit::from).collect())
+}
+
 impl fmt::Display for GitCommit {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Hash: {}, Author Email: {}, Message: {}",
-            self.hash, self.author_email, self.message
+            "Hash: {}{}, Author: {} <{}>, Message: {}",
+            self.hash,
+            if self.is_merge() { " (merge)" } else { "" },
+            self.author_name,
+            self.author_email,
+            self.message
+        )
+    }
+}
+impl FileState {
+    fn from_porcelain(code: char) -> Self {
+        match code {
+            'M' => FileState::Modified,
+            'A' | 'C' => FileState::Added,
+            'D' => FileState::Deleted,
+            'R' => FileState::Renamed,
+            'T' => FileState::TypeChanged,
+            'U' => FileState::Conflicted,
+            '?' => FileState::Untracked,
+            '!' => FileState::Ignored,
+            _ => FileState::Unmodified,
+        }
+    }
+}
+
+impl FileStatus {
+    pub fn new(
+        path: String,
+        orig_path: Option<String>,
+        index: FileState,
+        worktree: FileState,
+    ) -> Self {
+        Self {
+            path,
+            orig_path,
+            index,
+            worktree,
+        }
+    }
+
+    /// Parses one line of `git status --porcelain` output, as stored by versions up to 0.6.1.
+    pub fn from_porcelain_line(line: &str) -> Option<Self> {
+        let mut chars = line.chars();
+        let x = chars.next()?;
+        let y = chars.next()?;
+        let rest = line.get(3..)?;
+        let (orig_path, path) = match rest.split_once(" -> ") {
+            Some((from, to)) => (Some(from.to_string()), to.to_string()),
+            None => (None, rest.to_string()),
+        };
+        let conflicted = matches!((x, y), ('D', 'D') | ('A', 'A') | ('U', _) | (_, 'U'));
+        let (index, worktree) = if conflicted {
+            (FileState::Conflicted, FileState::Conflicted)
+        } else if x == '?' || x == '!' {
+            (FileState::Unmodified, FileState::from_porcelain(x))
+        } else {
+            (FileState::from_porcelain(x), FileState::from_porcelain(y))
+        };
+        Some(Self::new(path, orig_path, index, worktree))
+    }
+
+    pub fn is_staged(&self) -> bool {
+        !matches!(
+            self.index,
+            FileState::Unmodified
+                | FileState::Conflicted
+                | FileState::Untracked
+                | FileState::Ignored
+        )
+    }
+
+    pub fn is_modified(&self) -> bool {
+        matches!(
+            self.worktree,
+            FileState::Modified | FileState::Deleted | FileState::Renamed | FileState::TypeChanged
+        )
+    }
+}
+
+impl fmt::Display for FileState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FileState::Unmodified => "unmodified",
+            FileState::Modified => "modified",
+            FileState::Added => "added",
+            FileState::Deleted => "deleted",
+            FileState::Renamed => "renamed",
+            FileState::TypeChanged => "typechange",
+            FileState::Untracked => "untracked",
+            FileState::Ignored => "ignored",
+            FileState::Conflicted => "conflicted",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.orig_path {
+            Some(ref orig_path) => write!(
+                f,
+                "{} -> {} (index: {}, worktree: {})",
+                orig_path, self.path, self.index, self.worktree
+            ),
+            None => write!(
+                f,
+                "{} (index: {}, worktree: {})",
+                self.path, self.index, self.worktree
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Copied => "copied",
+            ChangeKind::TypeChanged => "type changed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for FileChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref old_path) = self.old_path {
+            write!(f, "{} -> ", old_path)?;
+        }
+        if self.binary {
+            write!(f, "{} ({}, binary)", self.path, self.kind)
+        } else {
+            write!(
+                f,
+                "{} ({}, +{}/-{})",
+                self.path, self.kind, self.insertions, self.deletions
+            )
+        }
+    }
+}
+
+impl StatusSummary {
+    pub fn from_entries(entries: &[FileStatus]) -> Self {
+        let mut summary = Self::default();
+        for entry in entries {
+            if entry.worktree == FileState::Conflicted {
+                summary.conflicted += 1;
+                continue;
+            }
+            if entry.worktree == FileState::Untracked {
+                summary.untracked += 1;
+            }
+            if entry.worktree == FileState::Ignored {
+                summary.ignored += 1;
+            }
+            if entry.is_staged() {
+                summary.staged += 1;
+            }
+            if entry.is_modified() {
+                summary.modified += 1;
+            }
+        }
+        summary
+    }
+
+    pub fn count(&self, category: StatusCategory) -> usize {
+        match category {
+            StatusCategory::Staged => self.staged,
+            StatusCategory::Modified => self.modified,
+            StatusCategory::Untracked => self.untracked,
+            StatusCategory::Conflicted => self.conflicted,
+            StatusCategory::Ignored => self.ignored,
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.staged + self.modified + self.untracked + self.conflicted == 0
+    }
+}
+
+impl fmt::Display for StatusSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "clean");
+        }
+        let mut parts = Vec::new();
+        if self.staged > 0 {
+            parts.push(format!("{} staged", self.staged));
+        }
+        if self.modified > 0 {
+            parts.push(format!("{} modified", self.modified));
+        }
+        if self.untracked > 0 {
+            parts.push(format!("{} untracked", self.untracked));
+        }
+        if self.conflicted > 0 {
+            let noun = if self.conflicted == 1 {
+                "conflict"
+            } else {
+                "conflicts"
+            };
+            parts.push(format!("{} {}", self.conflicted, noun));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl std::str::FromStr for StatusCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "staged" => Ok(StatusCategory::Staged),
+            "modified" => Ok(StatusCategory::Modified),
+            "untracked" => Ok(StatusCategory::Untracked),
+            "conflicted" => Ok(StatusCategory::Conflicted),
+            "ignored" => Ok(StatusCategory::Ignored),
+            _ => Err(format!(
+                "Unknown status category '{}', expected one of: staged, modified, untracked, conflicted, ignored",
+                s
+            )),
+        }
+    }
+}
+
+impl BranchInfo {
+    pub fn new(name: String, upstream: Option<String>, ahead: usize, behind: usize) -> Self {
+        Self {
+            name,
+            upstream,
+            ahead,
+            behind,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.ahead == 0 && self.behind == 0
+    }
+}
+
+impl fmt::Display for BranchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.upstream {
+            Some(ref upstream) => write!(
+                f,
+                "{} -> {} (+{}/-{})",
+                self.name, upstream, self.ahead, self.behind
+            ),
+            None => write!(f, "{} (no upstream)", self.name),
+        }
+    }
+}
+
+impl fmt::Display for FetchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchStatus::Skipped => write!(f, "fetch skipped"),
+            FetchStatus::Fetched => write!(f, "fetched"),
+            FetchStatus::NoRemote => write!(f, "no remote"),
+            FetchStatus::Failed(err) => write!(f, "fetch failed: {}", err),
+            FetchStatus::TimedOut => write!(f, "fetch timed out"),
+        }
+    }
+}
+
+impl BareRepoInfo {
+    pub fn new(tags: usize, last_commit: Option<DateTime<Utc>>, size_bytes: u64) -> Self {
+        Self {
+            tags,
+            last_commit,
+            size_bytes,
+        }
+    }
+}
+
+impl fmt::Display for BareRepoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let last_commit = match self.last_commit {
+            Some(last_commit) => last_commit.format("%Y-%m-%d").to_string(),
+            None => "none".to_string(),
+        };
+        write!(
+            f,
+            "bare, {} tags, last commit {}, {:.1} MiB",
+            self.tags,
+            last_commit,
+            self.size_bytes as f64 / (1024.0 * 1024.0)
         )
     }
 }
+
+impl fmt::Display for RepoKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RepoKind::Standalone => "standalone",
+            RepoKind::Nested => "nested in",
+            RepoKind::Submodule => "submodule of",
+            RepoKind::Worktree => "worktree of",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn parse_porcelain_status(status: &str) -> Vec<FileStatus> {
+    status
+        .lines()
+        .filter_map(FileStatus::from_porcelain_line)
+        .collect()
+}
+
 impl GitRepoInfo {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         path: String,
         origin_url: Option<String>,
-        status: String,
+        root_commit: Option<String>,
+        status: Vec<FileStatus>,
         unpushed_commits: String,
         remote_updates: String,
+        branches: Vec<BranchInfo>,
+        default_branch: Option<String>,
+        head: Option<HistoryTip>,
+        last_fetch: Option<DateTime<Utc>>,
+        fetch_status: FetchStatus,
+        kind: RepoKind,
+        parent: Option<String>,
+        bare: Option<BareRepoInfo>,
         app_version: Option<Version>,
         commits: Option<Vec<GitCommit>>,
+        history_tips: Vec<HistoryTip>,
+        latest_tag: Option<HistoryTip>,
         languages: Option<Languages>,
     ) -> Self {
         let app_version = app_version.unwrap_or_else(|| {
@@ -155,33 +892,181 @@ impl GitRepoInfo {
         Self {
             path: path.trim_end_matches('/').to_string(),
             origin_url: origin_url.unwrap_or_default(),
+            root_commit,
             status,
             unpushed_commits,
             remote_updates,
+            branches,
+            default_branch,
+            head,
+            last_fetch,
+            fetch_status,
+            kind,
+            parent,
+            bare,
             app_version,
             commits,
+            history_tips,
+            latest_tag,
             languages,
         }
     }
+
+    pub fn status_summary(&self) -> StatusSummary {
+        StatusSummary::from_entries(&self.status)
+    }
+
+    /// Conventional commit types per ISO week (`2024-W07`), merges left out.
+    /// Commits not following the convention count as `other`.
+    pub fn commit_kinds_per_week(&self) -> BTreeMap<String, BTreeMap<String, usize>> {
+        let mut weeks: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        for commit in self.commits.iter().flatten() {
+            let Some(time) = commit.time.to_datetime() else {
+                continue;
+            };
+            if commit.is_merge() {
+                continue;
+            }
+            let week = time.iso_week();
+            let kind = match commit.conventional {
+                Some(ref conventional) => conventional.kind.clone(),
+                None => "other".to_string(),
+            };
+            *weeks
+                .entry(format!("{}-W{:02}", week.year(), week.week()))
+                .or_default()
+                .entry(kind)
+                .or_default() += 1;
+        }
+        weeks
+    }
+
+    /// Breaking commits not contained in the latest tag, or all of them without a tag.
+    pub fn breaking_since_tag(&self) -> Vec<&GitCommit> {
+        let commits: HashMap<&str, &GitCommit> = self
+            .commits
+            .iter()
+            .flatten()
+            .map(|commit| (commit.hash.as_str(), commit))
+            .collect();
+        let mut released = HashSet::new();
+        let mut pending: Vec<&str> = self.latest_tag.iter().map(|tag| tag.id.as_str()).collect();
+        while let Some(hash) = pending.pop() {
+            if !released.insert(hash) {
+                continue;
+            }
+            if let Some(commit) = commits.get(hash) {
+                pending.extend(commit.parents.iter().map(|parent| parent.as_str()));
+            }
+        }
+        self.commits
+            .iter()
+            .flatten()
+            .filter(|commit| commit.is_breaking() && !released.contains(commit.hash.as_str()))
+            .collect()
+    }
+
+    /// Commits and lines per author, most commits first. Co-authors are credited with
+    /// `co_author_weight` of each commit, the author always with all of it.
+    pub fn author_stats(&self, co_author_weight: f64, include_merges: bool) -> Vec<AuthorStats> {
+        let mut authors: HashMap<String, AuthorStats> = HashMap::new();
+        let mut credit = |name: &str, email: &str, commit: &GitCommit, weight: f64| {
+            let entry = authors
+                .entry(email.to_lowercase())
+                .or_insert_with(|| AuthorStats {
+                    email: email.to_string(),
+                    ..AuthorStats::default()
+                });
+            if entry.name.is_empty() {
+                entry.name = name.to_string();
+            }
+            entry.commits += weight;
+            entry.insertions += commit.insertions as f64 * weight;
+            entry.deletions += commit.deletion as f64 * weight;
+        };
+        for commit in self.commits.iter().flatten() {
+            if commit.is_merge() && !include_merges {
+                continue;
+            }
+            credit(&commit.author_name, &commit.author_email, commit, 1.0);
+            if co_author_weight > 0.0 {
+                for co_author in &commit.co_authors {
+                    // Authors sometimes list themselves, do not count them twice.
+                    if !co_author.email.eq_ignore_ascii_case(&commit.author_email) {
+                        credit(&co_author.name, &co_author.email, commit, co_author_weight);
+                    }
+                }
+            }
+        }
+        let mut authors: Vec<AuthorStats> = authors.into_values().collect();
+        authors.sort_by(|a, b| {
+            b.commits
+                .total_cmp(&a.commits)
+                .then_with(|| a.email.cmp(&b.email))
+        });
+        authors
+    }
+
+    /// Per-path totals over the stored commits, most frequently changed first.
+    pub fn file_churn(&self) -> Vec<FileChurn> {
+        let mut churn: HashMap<&str, FileChurn> = HashMap::new();
+        for change in self
+            .commits
+            .iter()
+            .flatten()
+            .flat_map(|commit| &commit.files)
+        {
+            let entry = churn.entry(&change.path).or_insert_with(|| FileChurn {
+                path: change.path.clone(),
+                ..FileChurn::default()
+            });
+            entry.commits += 1;
+            entry.insertions += change.insertions;
+            entry.deletions += change.deletions;
+        }
+        let mut churn: Vec<FileChurn> = churn.into_values().collect();
+        churn.sort_by(|a, b| {
+            b.commits
+                .cmp(&a.commits)
+                .then((b.insertions + b.deletions).cmp(&(a.insertions + a.deletions)))
+                .then(a.path.cmp(&b.path))
+        });
+        churn
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GitRepoSummary {
     pub path: String,
     pub origin_url: String,
-    pub status_lines: i32,
+    pub root_commit: Option<String>,
+    pub status: StatusSummary,
     pub unpushed_commits_lines: i32,
     pub remote_updates_lines: i32,
+    pub branches: Vec<BranchInfo>,
+    pub default_branch: Option<String>,
+    pub fetch_status: FetchStatus,
+    pub kind: RepoKind,
+    pub parent: Option<String>,
+    pub bare: Option<BareRepoInfo>,
     pub app_version: Version,
 }
 
 impl GitRepoSummary {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         path: String,
         origin_url: Option<String>,
-        status_lines: i32,
+        status: StatusSummary,
         unpushed_commits_lines: i32,
         remote_updates_lines: i32,
+        branches: Vec<BranchInfo>,
+        default_branch: Option<String>,
+        fetch_status: FetchStatus,
+        kind: RepoKind,
+        parent: Option<String>,
+        bare: Option<BareRepoInfo>,
+        root_commit: Option<String>,
     ) -> Self {
         let app_version = {
             let version_str = env!("CARGO_PKG_VERSION");
@@ -192,16 +1077,366 @@ impl GitRepoSummary {
         Self {
             path: path.trim_end_matches('/').to_string(),
             origin_url: origin_url.unwrap_or_default(),
-            status_lines,
+            status,
             unpushed_commits_lines,
             remote_updates_lines,
+            branches,
+            default_branch,
+            fetch_status,
+            kind,
+            parent,
+            bare,
+            root_commit,
             app_version,
         }
     }
+
+    pub fn from_repo(repo: &GitRepoInfo) -> Self {
+        Self::new(
+            repo.path.clone(),
+            Some(repo.origin_url.clone()),
+            repo.status_summary(),
+            repo.unpushed_commits.matches('\n').count() as i32,
+            repo.remote_updates.matches('\n').count() as i32,
+            repo.branches.clone(),
+            repo.default_branch.clone(),
+            repo.fetch_status.clone(),
+            repo.kind,
+            repo.parent.clone(),
+            repo.bare.clone(),
+            repo.root_commit.clone(),
+        )
+    }
+}
+
+/// Point-in-time state of a repo, one is appended on every check.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RepoSnapshot {
+    pub time: DateTime<Utc>,
+    pub status: StatusSummary,
+    /// Of the checked out branch against its upstream.
+    pub ahead: usize,
+    pub behind: usize,
+    pub head: Option<HistoryTip>,
+    /// Lines of code per language.
+    pub languages: BTreeMap<String, usize>,
+}
+
+impl RepoSnapshot {
+    pub fn from_repo(repo: &GitRepoInfo, time: DateTime<Utc>) -> Self {
+        let head_branch = repo
+            .head
+            .as_ref()
+            .and_then(|head| repo.branches.iter().find(|branch| branch.name == head.name));
+        Self {
+            time,
+            status: repo.status_summary(),
+            ahead: head_branch.map(|branch| branch.ahead).unwrap_or(0),
+            behind: head_branch.map(|branch| branch.behind).unwrap_or(0),
+            head: repo.head.clone(),
+            languages: repo
+                .languages
+                .iter()
+                .flatten()
+                .map(|(name, language)| (name.to_string(), language.code))
+                .collect(),
+        }
+    }
+}
+
+/// Snapshot keys are the repo path, a NUL and the big endian timestamp, so a prefix scan
+/// yields one repo's snapshots in time order.
+fn snapshot_prefix(path: &str) -> Vec<u8> {
+    let mut key = path.as_bytes().to_vec();
+    key.push(0);
+    key
+}
+
+fn snapshot_key(path: &str, time: DateTime<Utc>) -> Vec<u8> {
+    let mut key = snapshot_prefix(path);
+    key.extend_from_slice(&time.timestamp_micros().to_be_bytes());
+    key
+}
+
+/// Schema version written in front of every `repo_db` record, bump it with each layout
+/// change and register a migration for the previous one.
+pub const SCHEMA_VERSION: u16 = 6;
+const SCHEMA_MAGIC: &[u8; 4] = b"GSDB";
+
+/// Converts a record stored with an older schema into the current `GitRepoInfo`.
+struct Migration {
+    from: u16,
+    layout: &'static str,
+    migrate: fn(&[u8]) -> Result<GitRepoInfo, bincode::Error>,
+}
+
+/// Layouts from before records carried a version are numbered in release order.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        layout: "0.3.0",
+        migrate: migrate_v030,
+    },
+    Migration {
+        from: 2,
+        layout: "0.5.1",
+        migrate: migrate_v051,
+    },
+    Migration {
+        from: 3,
+        layout: "0.6.1",
+        migrate: migrate_v061,
+    },
+    Migration {
+        from: 4,
+        layout: "schema 4",
+        migrate: migrate_v4,
+    },
+    Migration {
+        from: 5,
+        layout: "schema 5",
+        migrate: migrate_v5,
+    },
+];
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub current: usize,
+    /// Records rewritten, by the schema version they were stored with.
+    pub migrated: BTreeMap<u16, usize>,
+    /// Records stored under a symlinked path, moved to the canonical one.
+    pub rekeyed: usize,
+    /// Records that failed to load and were left untouched.
+    pub skipped: usize,
+}
+
+pub fn schema_layout(version: u16) -> &'static str {
+    MIGRATIONS
+        .iter()
+        .find(|migration| migration.from == version)
+        .map(|migration| migration.layout)
+        .unwrap_or("current")
+}
+
+fn encode_repo_info(repo: &GitRepoInfo) -> Result<Vec<u8>, bincode::Error> {
+    let mut data = SCHEMA_MAGIC.to_vec();
+    data.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut data, repo)?;
+    Ok(data)
+}
+
+/// Summaries are derived data, so only the current version is read; older ones get rebuilt.
+fn encode_summary(summary: &GitRepoSummary) -> Result<Vec<u8>, bincode::Error> {
+    let mut data = SCHEMA_MAGIC.to_vec();
+    data.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut data, summary)?;
+    Ok(data)
+}
+
+fn decode_summary(data: &[u8]) -> Result<GitRepoSummary, bincode::Error> {
+    match data.strip_prefix(SCHEMA_MAGIC) {
+        Some(rest) if rest.starts_with(&SCHEMA_VERSION.to_le_bytes()) => {
+            bincode::deserialize(&rest[2..])
+        }
+        _ => Err(Box::new(bincode::ErrorKind::Custom(
+            "outdated summary record".to_string(),
+        ))),
+    }
+}
+
+/// Version written in front of every snapshot, bump it when `RepoSnapshot` changes.
+const SNAPSHOT_VERSION: u16 = 1;
+
+fn encode_snapshot(snapshot: &RepoSnapshot) -> Result<Vec<u8>, bincode::Error> {
+    let mut data = SCHEMA_MAGIC.to_vec();
+    data.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut data, snapshot)?;
+    Ok(data)
+}
+
+fn decode_snapshot(data: &[u8]) -> Result<RepoSnapshot, bincode::Error> {
+    match data.strip_prefix(SCHEMA_MAGIC) {
+        Some(rest) if rest.starts_with(&SNAPSHOT_VERSION.to_le_bytes()) => {
+            bincode::deserialize(&rest[2..])
+        }
+        _ => Err(Box::new(bincode::ErrorKind::Custom(
+            "unknown snapshot version".to_string(),
+        ))),
+    }
+}
+
+/// Decodes a record of any known schema, returning the version it was stored with.
+fn decode_repo_info(data: &[u8]) -> Result<(GitRepoInfo, u16), bincode::Error> {
+    let (version, payload) = match data.strip_prefix(SCHEMA_MAGIC) {
+        Some(rest) if rest.len() >= 2 => (u16::from_le_bytes([rest[0], rest[1]]), &rest[2..]),
+        _ => (legacy_schema_version(data)?, data),
+    };
+    if version == SCHEMA_VERSION {
+        return Ok((bincode::deserialize(payload)?, version));
+    }
+    let migration = MIGRATIONS
+        .iter()
+        .find(|migration| migration.from == version)
+        .ok_or_else(|| {
+            Box::new(bincode::ErrorKind::Custom(format!(
+                "unknown schema version {}",
+                version
+            )))
+        })?;
+    debug!(
+        "{}",
+        format!(
+            "WARNING: Record stored with the {} layout. Run `gitstatus db migrate` to update the database!",
+            migration.layout
+        )
+        .yellow()
+    );
+    Ok(((migration.migrate)(payload)?, version))
+}
+
+/// Unversioned records are told apart by which old layout decodes them without leftovers.
+fn legacy_schema_version(data: &[u8]) -> Result<u16, bincode::Error> {
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes();
+    if options.deserialize::<GitRepoInfoV061>(data).is_ok() {
+        return Ok(3);
+    }
+    if options.deserialize::<GitRepoInfoV051>(data).is_ok() {
+        return Ok(2);
+    }
+    options.deserialize::<GitRepoInfoV030>(data).map(|_| 1)
+}
+
+fn migrate_v5(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
+    let repo: GitRepoInfoV5 = bincode::deserialize(data)?;
+    Ok(GitRepoInfo::new(
+        repo.path,
+        Some(repo.origin_url),
+        None,
+        repo.status,
+        repo.unpushed_commits,
+        repo.remote_updates,
+        repo.branches,
+        repo.default_branch,
+        repo.head,
+        repo.last_fetch,
+        repo.fetch_status,
+        repo.kind,
+        repo.parent,
+        repo.bare,
+        Some(repo.app_version),
+        repo.commits,
+        repo.history_tips,
+        repo.latest_tag,
+        repo.languages,
+    ))
+}
+
+fn migrate_v4(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
+    let repo: GitRepoInfoV4 = bincode::deserialize(data)?;
+    Ok(GitRepoInfo::new(
+        repo.path,
+        Some(repo.origin_url),
+        None,
+        repo.status,
+        repo.unpushed_commits,
+        repo.remote_updates,
+        repo.branches,
+        repo.default_branch,
+        None,
+        repo.last_fetch,
+        repo.fetch_status,
+        repo.kind,
+        repo.parent,
+        repo.bare,
+        Some(repo.app_version),
+        repo.commits,
+        repo.history_tips,
+        repo.latest_tag,
+        repo.languages,
+    ))
+}
+
+fn migrate_v061(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
+    let repo: GitRepoInfoV061 = bincode::deserialize(data)?;
+    Ok(GitRepoInfo::new(
+        repo.path,
+        Some(repo.origin_url),
+        None,
+        parse_porcelain_status(&repo.status),
+        repo.unpushed_commits,
+        repo.remote_updates,
+        Vec::new(),
+        None,
+        None,
+        None,
+        FetchStatus::Skipped,
+        RepoKind::Standalone,
+        None,
+        None,
+        Some(repo.app_version),
+        migrate_commits(repo.commits),
+        Vec::new(),
+        None,
+        repo.languages,
+    ))
 }
+
+fn migrate_v051(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
+    let repo: GitRepoInfoV051 = bincode::deserialize(data)?;
+    Ok(GitRepoInfo::new(
+        repo.path,
+        Some(repo.origin_url),
+        None,
+        parse_porcelain_status(&repo.status),
+        repo.unpushed_commits,
+        repo.remote_updates,
+        Vec::new(),
+        None,
+        None,
+        None,
+        FetchStatus::Skipped,
+        RepoKind::Standalone,
+        None,
+        None,
+        Some(repo.app_version),
+        migrate_commits(repo.commits),
+        Vec::new(),
+        None,
+        None,
+    ))
+}
+
+fn migrate_v030(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
+    let repo: GitRepoInfoV030 = bincode::deserialize(data)?;
+    Ok(GitRepoInfo::new(
+        repo.path,
+        Some(repo.origin_url),
+        None,
+        parse_porcelain_status(&repo.status),
+        repo.unpushed_commits,
+        repo.remote_updates,
+        Vec::new(),
+        None,
+        None,
+        None,
+        FetchStatus::Skipped,
+        RepoKind::Standalone,
+        None,
+        None,
+        Some(repo.app_version),
+        None,
+        Vec::new(),
+        None,
+        None,
+    ))
+}
+
 pub struct GitDatabase {
     db: Db,
-    summary_db: Db,
+    summary_db: Tree,
+    snapshot_db: Tree,
 }
 
 impl Clone for GitDatabase {
@@ -209,6 +1444,7 @@ impl Clone for GitDatabase {
         Self {
             db: self.db.clone(),
             summary_db: self.summary_db.clone(),
+            snapshot_db: self.snapshot_db.clone(),
         }
     }
 }
@@ -217,18 +1453,218 @@ impl GitDatabase {
     pub fn new(path: &Path) -> Result<Self, GitDatabaseError> {
         let _ = std::fs::create_dir_all(path);
         let db = sled::open(path.join("repo_db"))?;
-        let summary_db = sled::open(path.join("summary_db"))?;
-        Ok(Self { db, summary_db })
+        // A tree of the same database, so records and summaries are written together.
+        let summary_db = db.open_tree("summary")?;
+        let snapshot_db = db.open_tree("snapshots")?;
+        let gitdb = Self {
+            db,
+            summary_db,
+            snapshot_db,
+        };
+        if gitdb.summary_is_stale()? {
+            gitdb.summary_repos_table()?;
+        }
+        // Summaries used to live in a database of their own, rebuilt above from the records.
+        let old_summary_db = path.join("summary_db");
+        if old_summary_db.is_dir() {
+            if let Err(e) = fs::remove_dir_all(&old_summary_db) {
+                eprintln!("Failed to remove {}: {}", old_summary_db.display(), e);
+            }
+        }
+        Ok(gitdb)
     }
 
+    /// Saves the record and its summary, and appends a snapshot, in one transaction.
     pub fn save_to_db(&self, repo: &GitRepoInfo) -> Result<(), GitDatabaseError> {
-        self.db
-            .insert(repo.path.as_bytes(), bincode::serialize(repo)?)?;
+        self.write_repo(repo, Some(&RepoSnapshot::from_repo(repo, Utc::now())))
+    }
+
+    fn write_repo(
+        &self,
+        repo: &GitRepoInfo,
+        snapshot: Option<&RepoSnapshot>,
+    ) -> Result<(), GitDatabaseError> {
+        let record = encode_repo_info(repo)?;
+        let summary = encode_summary(&GitRepoSummary::from_repo(repo))?;
+        let snapshot = match snapshot {
+            Some(snapshot) => Some((
+                snapshot_key(&repo.path, snapshot.time),
+                encode_snapshot(snapshot)?,
+            )),
+            None => None,
+        };
+        let repos: &Tree = &self.db;
+        (repos, &self.summary_db, &self.snapshot_db)
+            .transaction(|(repos, summaries, snapshots)| {
+                repos.insert(repo.path.as_bytes(), record.as_slice())?;
+                summaries.insert(repo.path.as_bytes(), summary.as_slice())?;
+                if let Some((ref key, ref value)) = snapshot {
+                    snapshots.insert(key.as_slice(), value.as_slice())?;
+                }
+                Ok::<(), ConflictableTransactionError>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => {
+                    GitDatabaseError::SledError(e)
+                }
+            })
+    }
+
+    pub fn repo_paths(&self) -> Result<Vec<String>, GitDatabaseError> {
+        let mut paths = Vec::new();
+        for key in self.db.iter().keys() {
+            paths.push(String::from_utf8_lossy(&key?).to_string());
+        }
+        Ok(paths)
+    }
+
+    /// Removes the record and summary of a repo. Snapshots are kept so `history` still
+    /// works for repos that are gone.
+    pub fn remove_repo(&self, path: &str) -> Result<(), GitDatabaseError> {
+        let repos: &Tree = &self.db;
+        (repos, &self.summary_db)
+            .transaction(|(repos, summaries)| {
+                repos.remove(path.as_bytes())?;
+                summaries.remove(path.as_bytes())?;
+                Ok::<(), ConflictableTransactionError>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => {
+                    GitDatabaseError::SledError(e)
+                }
+            })?;
         Ok(())
     }
 
+    /// Stored repos with the given identity, other than `path` itself.
+    pub fn find_by_identity(
+        &self,
+        root_commit: &str,
+        origin_url: &str,
+        path: &str,
+    ) -> Result<Vec<String>, GitDatabaseError> {
+        let mut paths = Vec::new();
+        for result in self.summary_db.iter() {
+            let (_key, value) = result?;
+            let summary = decode_summary(&value)?;
+            if summary.path != path
+                && summary.root_commit.as_deref() == Some(root_commit)
+                && summary.origin_url == origin_url
+            {
+                paths.push(summary.path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Moves the record, summary and snapshots of a repo to a new path. Returns `false` when
+    /// there is nothing stored at `from`, e.g. because another check already moved it.
+    pub fn rename_repo(&self, from: &str, to: &str) -> Result<bool, GitDatabaseError> {
+        let repos: &Tree = &self.db;
+        let moved = (repos, &self.summary_db)
+            .transaction(|(repos, summaries)| {
+                let Some(value) = repos.remove(from.as_bytes())? else {
+                    return Ok(false);
+                };
+                summaries.remove(from.as_bytes())?;
+                let mut repo = Self::deserialize_git_repo_info(&value)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                repo.path = to.to_string();
+                let record = encode_repo_info(&repo)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                let summary = encode_summary(&GitRepoSummary::from_repo(&repo))
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                repos.insert(to.as_bytes(), record)?;
+                summaries.insert(to.as_bytes(), summary)?;
+                Ok(true)
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => GitDatabaseError::SledError(e),
+            })?;
+        if moved {
+            let prefix = snapshot_prefix(from);
+            for result in self.snapshot_db.scan_prefix(&prefix) {
+                let (key, value) = result?;
+                let mut new_key = snapshot_prefix(to);
+                new_key.extend_from_slice(&key[prefix.len()..]);
+                self.snapshot_db.insert(new_key, value)?;
+                self.snapshot_db.remove(key)?;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Snapshots of one repo, oldest first. Unreadable snapshots are skipped.
+    pub fn get_snapshots(&self, path: &str) -> Result<Vec<RepoSnapshot>, GitDatabaseError> {
+        let mut snapshots = Vec::new();
+        for result in self.snapshot_db.scan_prefix(snapshot_prefix(path)) {
+            let (_key, value) = result?;
+            match decode_snapshot(&value) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => eprintln!("Skipping unreadable snapshot of {}: {}", path, e),
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Drops snapshots older than `max_age` and all but the newest `max_count`, returning
+    /// how many were removed.
+    pub fn prune_snapshots(
+        &self,
+        path: &str,
+        max_age: Option<Duration>,
+        max_count: Option<usize>,
+    ) -> Result<usize, GitDatabaseError> {
+        let keys = self
+            .snapshot_db
+            .scan_prefix(snapshot_prefix(path))
+            .keys()
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut expired = keys.len().saturating_sub(max_count.unwrap_or(usize::MAX));
+        if let Some(max_age) = max_age {
+            let cutoff = chrono::Duration::from_std(max_age)
+                .ok()
+                .and_then(|max_age| Utc::now().checked_sub_signed(max_age));
+            if let Some(cutoff) = cutoff {
+                let cutoff = snapshot_key(path, cutoff);
+                let too_old = keys.iter().filter(|key| key.as_ref() < cutoff.as_slice());
+                expired = expired.max(too_old.count());
+            }
+        }
+        for key in &keys[..expired] {
+            self.snapshot_db.remove(key)?;
+        }
+        Ok(expired)
+    }
+
+    /// Applies `prune_snapshots` to every repo with snapshots, including repos whose
+    /// records were pruned or moved, returning how many were removed.
+    pub fn prune_all_snapshots(
+        &self,
+        max_age: Option<Duration>,
+        max_count: Option<usize>,
+    ) -> Result<usize, GitDatabaseError> {
+        let mut paths: Vec<String> = Vec::new();
+        for key in self.snapshot_db.iter().keys() {
+            let key = key?;
+            // Keys are the path, a `\0` and the 8 byte time.
+            let Some(path) = key.len().checked_sub(9).map(|len| &key[..len]) else {
+                continue;
+            };
+            let path = String::from_utf8_lossy(path);
+            if paths.last().map(String::as_str) != Some(&*path) {
+                paths.push(path.to_string());
+            }
+        }
+        let mut removed = 0;
+        for path in paths {
+            removed += self.prune_snapshots(&path, max_age, max_count)?;
+        }
+        Ok(removed)
+    }
+
     //TODO: Review this function
-    #[allow(dead_code)]
     pub fn load_from_db(&self) -> Result<Vec<GitRepoInfo>, GitDatabaseError> {
         let mut repos = Vec::new();
         for result in self.db.iter() {
@@ -240,56 +1676,70 @@ impl GitDatabase {
     }
 
     fn deserialize_git_repo_info(data: &[u8]) -> Result<GitRepoInfo, bincode::Error> {
-        match bincode::deserialize::<GitRepoInfo>(data) {
-            Ok(repo) => Ok(repo),
-            Err(e) => match *e {
-                bincode::ErrorKind::Io(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    match bincode::deserialize::<GitRepoInfoV051>(data) {
-                        Ok(repo_v051) => {
-                            debug!(
-                            "{}",
-                            format!("WARNING: Old version of data {}. Please run with Check command to update the repo!", repo_v051.app_version).yellow()
-                        );
-                            let new_repo = GitRepoInfo::new(
-                                repo_v051.path,
-                                Some(repo_v051.origin_url),
-                                repo_v051.status,
-                                repo_v051.unpushed_commits,
-                                repo_v051.remote_updates,
-                                Some(repo_v051.app_version),
-                                repo_v051.commits,
-                                None,
-                            );
-                            Ok(new_repo)
-                        }
-                        Err(e) => match *e {
-                            bincode::ErrorKind::Io(ref e)
-                                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
-                            {
-                                let repo_v030: GitRepoInfoV030 = bincode::deserialize(data)?;
-                                debug!(
-                                "{}",
-                                format!("WARNING: Old version of data {}. Please run with Check command to update the repo!", repo_v030.app_version).yellow()
-                            );
-                                let new_repo = GitRepoInfo::new(
-                                    repo_v030.path,
-                                    Some(repo_v030.origin_url),
-                                    repo_v030.status,
-                                    repo_v030.unpushed_commits,
-                                    repo_v030.remote_updates,
-                                    Some(repo_v030.app_version),
-                                    None,
-                                    None,
-                                );
-                                Ok(new_repo)
-                            }
-                            _ => Err(e),
-                        },
-                    }
+        decode_repo_info(data).map(|(repo, _)| repo)
+    }
+
+    /// Rewrites every record that is not at `SCHEMA_VERSION` and moves records stored under
+    /// a symlinked path to the canonical one, after copying every tree, summaries and
+    /// snapshots included, into a new database at `backup_path`. Records that fail to load
+    /// are reported and left as they are.
+    pub fn migrate(&self, backup_path: &Path) -> Result<MigrationReport, GitDatabaseError> {
+        let backup = sled::open(backup_path)?;
+        for name in self.db.tree_names() {
+            let tree = self.db.open_tree(&name)?;
+            let backup_tree = backup.open_tree(&name)?;
+            for result in tree.iter() {
+                let (key, value) = result?;
+                backup_tree.insert(key, value)?;
+            }
+        }
+        backup.flush()?;
+
+        let mut report = MigrationReport::default();
+        let mut unreadable = HashSet::new();
+        for result in self.db.iter() {
+            let (key, value) = result?;
+            let (repo, version) = match decode_repo_info(&value) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    eprintln!(
+                        "Skipping unreadable record {}: {}",
+                        String::from_utf8_lossy(&key),
+                        e
+                    );
+                    unreadable.insert(String::from_utf8_lossy(&key).to_string());
+                    continue;
                 }
-                _ => Err(e),
-            },
+            };
+            if version == SCHEMA_VERSION {
+                report.current += 1;
+                continue;
+            }
+            self.write_repo(&repo, None)?;
+            *report.migrated.entry(version).or_default() += 1;
+        }
+        report.skipped = unreadable.len();
+        for path in self.repo_paths()? {
+            if unreadable.contains(&path) {
+                continue;
+            }
+            let Ok(canonical) = fs::canonicalize(&path) else {
+                continue;
+            };
+            let canonical = canonical.display().to_string();
+            if canonical == path {
+                continue;
+            }
+            // A newer record under the canonical path wins.
+            if self.db.contains_key(canonical.as_bytes())? {
+                self.remove_repo(&path)?;
+            } else {
+                self.rename_repo(&path, &canonical)?;
+            }
+            report.rekeyed += 1;
         }
+        self.db.flush()?;
+        Ok(report)
     }
 
     pub fn get_repo_details(&self, path: PathBuf) -> Result<GitRepoInfo, GitDatabaseError> {
@@ -306,28 +1756,35 @@ impl GitDatabase {
         }
     }
 
-    pub fn summary_repos_table(&self) -> Result<(), GitDatabaseError> {
-        let mut repos_summary = Vec::new();
-
-        for result in self.db.iter() {
-            let (_key, value) = result?;
-            let repo: GitRepoInfo = bincode::deserialize(&value)?;
-            debug!("{:?}", repo.path);
-            let summary = GitRepoSummary::new(
-                repo.path,
-                Some(repo.origin_url.clone()),
-                repo.status.matches('\n').count() as i32,
-                repo.unpushed_commits.matches('\n').count() as i32,
-                repo.remote_updates.matches('\n').count() as i32,
-            );
-            repos_summary.push(summary);
+    /// Summaries are missing for databases written before they were kept alongside the
+    /// records, and outdated after a schema change.
+    fn summary_is_stale(&self) -> Result<bool, GitDatabaseError> {
+        match self.summary_db.first()? {
+            Some((_key, value)) => Ok(decode_summary(&value).is_err()),
+            None => Ok(!self.db.is_empty()),
         }
+    }
 
-        for summary in repos_summary {
-            self.summary_db
-                .insert(summary.path.as_bytes(), bincode::serialize(&summary)?)?;
+    /// Rebuilds all summaries from the records. Records that fail to load are reported and
+    /// left out instead of failing the whole table.
+    pub fn summary_repos_table(&self) -> Result<(), GitDatabaseError> {
+        self.summary_db.clear()?;
+        for result in self.db.iter() {
+            let (key, value) = result?;
+            match Self::deserialize_git_repo_info(&value) {
+                Ok(repo) => {
+                    debug!("{:?}", repo.path);
+                    let summary = encode_summary(&GitRepoSummary::from_repo(&repo))?;
+                    self.summary_db.insert(key, summary)?;
+                }
+                Err(e) => eprintln!(
+                    "Skipping unreadable record {}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ),
+            }
         }
-
+        self.summary_db.flush()?;
         Ok(())
     }
 
@@ -336,7 +1793,7 @@ impl GitDatabase {
 
         for result in self.summary_db.iter() {
             let (_key, value) = result?;
-            let summary: GitRepoSummary = bincode::deserialize(&value)?;
+            let summary = decode_summary(&value)?;
             repos.push(summary);
         }
 
@@ -344,6 +1801,350 @@ impl GitDatabase {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_commit(
+        hash: &str,
+        author: (&str, &str),
+        parents: usize,
+        insertions: usize,
+        deletions: usize,
+        message: &str,
+    ) -> GitCommit {
+        let time = SerializableTime(Time::new(1_700_000_000, 0));
+        GitCommit::new(
+            hash.to_string(),
+            author.0.to_string(),
+            author.1.to_string(),
+            time,
+            author.0.to_string(),
+            author.1.to_string(),
+            time,
+            (0..parents).map(|parent| parent.to_string()).collect(),
+            message.to_string(),
+            1,
+            insertions,
+            deletions,
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    fn test_repo(path: &str, commits: Vec<GitCommit>) -> GitRepoInfo {
+        GitRepoInfo::new(
+            path.to_string(),
+            Some("https://example.com/repo.git".to_string()),
+            None,
+            Vec::new(),
+            String::new(),
+            String::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+            FetchStatus::Skipped,
+            RepoKind::Standalone,
+            None,
+            None,
+            None,
+            Some(commits),
+            Vec::new(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_author_stats() {
+        let alice = ("Alice", "alice@example.com");
+        let bob = ("Bob", "bob@example.com");
+        let repo = test_repo(
+            "/repo",
+            vec![
+                test_commit(
+                    "1",
+                    alice,
+                    1,
+                    10,
+                    2,
+                    "feat: pair on it\n\nCo-authored-by: Bob <Bob@Example.com>",
+                ),
+                // Listing yourself as co-author does not count twice.
+                test_commit(
+                    "2",
+                    bob,
+                    1,
+                    4,
+                    0,
+                    "fix: solo\n\nCo-authored-by: Bob <bob@example.com>",
+                ),
+                test_commit("3", alice, 2, 100, 0, "Merge branch 'feat'"),
+            ],
+        );
+
+        let stats = repo.author_stats(0.5, false);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "Bob");
+        assert_eq!(stats[0].email, "Bob@Example.com");
+        assert_eq!(stats[0].commits, 1.5);
+        assert_eq!(stats[0].insertions, 9.0);
+        assert_eq!(stats[0].deletions, 1.0);
+        assert_eq!(stats[1].email, "alice@example.com");
+        assert_eq!(stats[1].commits, 1.0);
+        assert_eq!(stats[1].insertions, 10.0);
+
+        let stats = repo.author_stats(0.0, true);
+        assert_eq!(stats[0].email, "alice@example.com");
+        assert_eq!(stats[0].commits, 2.0);
+        assert_eq!(stats[0].insertions, 110.0);
+        assert_eq!(stats[1].commits, 1.0);
+        assert_eq!(stats[1].insertions, 4.0);
+    }
+
+    #[test]
+    fn test_prune_snapshots() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let gitdb = GitDatabase::new(dir.path()).unwrap();
+        let repo = test_repo("/repo", Vec::new());
+        let now = Utc::now();
+        let days = [400, 100, 10, 1, 0];
+        for days in days {
+            let time = now - chrono::Duration::days(days);
+            gitdb
+                .write_repo(&repo, Some(&RepoSnapshot::from_repo(&repo, time)))
+                .unwrap();
+        }
+        // Shares a prefix with `/repo` but is a different repo.
+        let other = test_repo("/repo-2", Vec::new());
+        gitdb.save_to_db(&other).unwrap();
+
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+        assert_eq!(gitdb.prune_snapshots("/repo", Some(year), None).unwrap(), 1);
+        assert_eq!(gitdb.prune_snapshots("/repo", None, None).unwrap(), 0);
+        assert_eq!(
+            gitdb.prune_snapshots("/repo", Some(year), Some(2)).unwrap(),
+            2
+        );
+        let kept: Vec<_> = gitdb
+            .get_snapshots("/repo")
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.time.timestamp_micros())
+            .collect();
+        let expected: Vec<_> = days[3..]
+            .iter()
+            .map(|days| (now - chrono::Duration::days(*days)).timestamp_micros())
+            .collect();
+        assert_eq!(kept, expected);
+        assert_eq!(gitdb.get_snapshots("/repo-2").unwrap().len(), 1);
+
+        // Snapshots of repos that are no longer stored expire as well.
+        let old = RepoSnapshot::from_repo(&other, now - chrono::Duration::days(400));
+        gitdb.write_repo(&other, Some(&old)).unwrap();
+        gitdb.remove_repo("/repo-2").unwrap();
+        assert_eq!(gitdb.prune_all_snapshots(Some(year), Some(1)).unwrap(), 2);
+        assert_eq!(gitdb.get_snapshots("/repo").unwrap().len(), 1);
+        assert_eq!(gitdb.get_snapshots("/repo-2").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_versions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let gitdb = GitDatabase::new(dir.path()).unwrap();
+        let repo = test_repo("/repo", Vec::new());
+        let now = Utc::now();
+        gitdb
+            .snapshot_db
+            .insert(
+                snapshot_key("/repo", now - chrono::Duration::days(1)),
+                b"GSDB\xff\xffgarbage".to_vec(),
+            )
+            .unwrap();
+        gitdb.save_to_db(&repo).unwrap();
+
+        // The unreadable snapshot is skipped instead of failing the whole history.
+        let snapshots = gitdb.get_snapshots("/repo").unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(
+            snapshots[0],
+            RepoSnapshot::from_repo(&repo, snapshots[0].time)
+        );
+    }
+
+    #[test]
+    fn test_parse_porcelain_status() {
+        let entries = parse_porcelain_status(
+            "M  staged.rs\n M modified.rs\nR  old.rs -> new.rs\n?? untracked.rs\nUU conflict.rs\n",
+        );
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].index, FileState::Modified);
+        assert_eq!(entries[1].worktree, FileState::Modified);
+        assert_eq!(entries[2].path, "new.rs");
+        assert_eq!(entries[2].orig_path.as_deref(), Some("old.rs"));
+        assert_eq!(entries[3].worktree, FileState::Untracked);
+        assert_eq!(entries[4].index, FileState::Conflicted);
+
+        let summary = StatusSummary::from_entries(&entries);
+        assert_eq!(summary.staged, 2);
+        assert_eq!(summary.modified, 1);
+        assert_eq!(summary.untracked, 1);
+        assert_eq!(summary.conflicted, 1);
+        assert_eq!(
+            summary.to_string(),
+            "2 staged, 1 modified, 1 untracked, 1 conflict"
+        );
+    }
+
+    #[test]
+    fn test_parse_commit_message() {
+        let message = "feat(db)!: drop legacy layout\n\nLonger body.\n\nRefs: #12\nCo-authored-by: Jane <jane@example.com>\n  continued\n";
+        let trailers = parse_trailers(message);
+        assert_eq!(trailers.len(), 2);
+        assert_eq!(trailers[0].key, "Refs");
+        assert_eq!(trailers[1].value, "Jane <jane@example.com> continued");
+        let conventional = ConventionalCommit::parse(message, &trailers).unwrap();
+        assert_eq!(conventional.kind, "feat");
+        assert_eq!(conventional.scope.as_deref(), Some("db"));
+        assert!(conventional.breaking);
+        assert_eq!(conventional.subject, "drop legacy layout");
+
+        let message = "fix: typo\n\nBREAKING CHANGE: renamed flag";
+        let trailers = parse_trailers(message);
+        assert!(
+            ConventionalCommit::parse(message, &trailers)
+                .unwrap()
+                .breaking
+        );
+
+        assert!(ConventionalCommit::parse("Merge branch 'main'", &[]).is_none());
+        assert!(parse_trailers("Fixes: something").is_empty());
+        assert!(parse_trailers("subject\n\nJust a body line.").is_empty());
+
+        let co_author = CoAuthor::parse("Jane Doe <jane@example.com>").unwrap();
+        assert_eq!(co_author.name, "Jane Doe");
+        assert_eq!(co_author.email, "jane@example.com");
+        assert!(CoAuthor::parse("Jane Doe").is_none());
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let version = Version::parse("0.6.1").unwrap();
+        let v061 = bincode::serialize(&GitRepoInfoV061 {
+            path: "/repo".to_string(),
+            status: " M file.rs\n".to_string(),
+            origin_url: String::new(),
+            unpushed_commits: String::new(),
+            remote_updates: String::new(),
+            app_version: version.clone(),
+            commits: Some(vec![GitCommitV061 {
+                hash: "abc123".to_string(),
+                author_email: "dev@example.com".to_string(),
+                time: 1_700_000_000,
+                message: "Old commit".to_string(),
+                file_changes: 2,
+                insertions: 10,
+                deletion: 3,
+            }]),
+            languages: None,
+        })
+        .unwrap();
+        let v030 = bincode::serialize(&GitRepoInfoV030 {
+            path: "/repo".to_string(),
+            status: String::new(),
+            origin_url: String::new(),
+            unpushed_commits: String::new(),
+            remote_updates: String::new(),
+            app_version: version,
+        })
+        .unwrap();
+
+        let (repo, schema) = decode_repo_info(&v061).unwrap();
+        assert_eq!(schema, 3);
+        assert_eq!(repo.status.len(), 1);
+        // Fields that were not recorded back then come back empty.
+        let commits = repo.commits.as_ref().unwrap();
+        assert_eq!(commits.len(), 1);
+        let commit = &commits[0];
+        assert_eq!(commit.time.0.seconds(), 1_700_000_000);
+        assert_eq!(commit.commit_time, commit.time);
+        assert_eq!(commit.author_name, "");
+        assert_eq!(commit.committer_name, "");
+        assert_eq!(commit.committer_email, "dev@example.com");
+        assert!(commit.parents.is_empty());
+        assert_eq!(
+            (commit.insertions, commit.deletion, commit.file_changes),
+            (10, 3, 2)
+        );
+        assert_eq!(decode_repo_info(&v030).unwrap().1, 1);
+
+        let (repo, schema) = decode_repo_info(&encode_repo_info(&repo).unwrap()).unwrap();
+        assert_eq!(schema, SCHEMA_VERSION);
+        assert_eq!(repo.path, "/repo");
+        assert_eq!(repo.commits.unwrap()[0].time.0.seconds(), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_serializable_time() {
+        // UTC+05:30, the offset must survive the round trip.
+        let time = SerializableTime(Time::new(1_700_000_000, 330));
+        let decoded: SerializableTime =
+            bincode::deserialize(&bincode::serialize(&time).unwrap()).unwrap();
+        assert_eq!(decoded, time);
+        assert_eq!(decoded.0.offset_minutes(), 330);
+        assert_eq!(
+            decoded.to_datetime().unwrap().to_rfc3339(),
+            "2023-11-15T03:43:20+05:30"
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let gitdb = GitDatabase::new(&dir.path().join("db")).unwrap();
+        let v030 = bincode::serialize(&GitRepoInfoV030 {
+            path: "/repo".to_string(),
+            status: String::new(),
+            origin_url: String::new(),
+            unpushed_commits: String::new(),
+            remote_updates: String::new(),
+            app_version: Version::parse("0.3.0").unwrap(),
+        })
+        .unwrap();
+        gitdb.db.insert("/repo", v030.clone()).unwrap();
+        gitdb.db.insert("/broken", b"garbage".to_vec()).unwrap();
+        let snapshot = snapshot_key("/repo", Utc::now());
+        gitdb
+            .snapshot_db
+            .insert(&snapshot, b"snapshot".to_vec())
+            .unwrap();
+
+        // The unreadable record is reported and left alone, the rest is still migrated.
+        let report = gitdb.migrate(&dir.path().join("backup")).unwrap();
+        assert_eq!(report.migrated, BTreeMap::from([(1, 1)]));
+        assert_eq!((report.current, report.skipped), (0, 1));
+        assert_eq!(
+            decode_repo_info(&gitdb.db.get("/repo").unwrap().unwrap())
+                .unwrap()
+                .1,
+            SCHEMA_VERSION
+        );
+        assert!(gitdb.db.contains_key("/broken").unwrap());
+
+        // Every tree is backed up as it was before the migration.
+        let backup = sled::open(dir.path().join("backup")).unwrap();
+        assert_eq!(backup.get("/repo").unwrap().unwrap(), v030);
+        assert!(backup.contains_key("/broken").unwrap());
+        let snapshots = backup.open_tree("snapshots").unwrap();
+        assert_eq!(
+            snapshots.get(&snapshot).unwrap().unwrap(),
+            b"snapshot".to_vec()
+        );
+        assert_eq!(backup.open_tree("summary").unwrap().len(), 0);
+    }
+}
+
 //#[cfg(test)]
 //mod tests {
 //    use super::*;
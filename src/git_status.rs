@@ -1,22 +1,41 @@
+use crate::config::{
+    expand_tilde, Fetch, History, MergeStats, Scan, ScanRoot, HISTORY_HEAD, HISTORY_LOCAL,
+    HISTORY_REMOTE,
+};
 use crate::git_database::{
-    GitCommit, GitDatabase, GitDatabaseError, GitRepoInfo, SerializableTime,
+    BareRepoInfo, BranchInfo, ChangeKind, FetchStatus, FileChange, FileState, FileStatus,
+    GitCommit, GitDatabase, GitDatabaseError, GitRepoInfo, HistoryTip, RepoKind, SerializableTime,
 };
+use chrono::{DateTime, Utc};
 use colored::Colorize;
-use git2::Repository;
+use git2::{
+    BranchType, Commit, Cred, CredentialType, Delta, DescribeFormatOptions, DescribeOptions, Diff,
+    DiffFindOptions, ErrorCode, FetchOptions, Oid, Patch, Reference, RemoteCallbacks, Repository,
+    RepositoryOpenFlags, Sort, Status, StatusOptions, Tree,
+};
+use glob::Pattern;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::debug;
 use semver::Version;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use tokei::{Config as TokeiCfg, Languages};
+use tokio::sync::Semaphore;
+use tokio::task;
 
 #[derive(Debug)]
 pub enum GitStatusError {
     InvalidDetailLevel,
     Git2(git2::Error),
     NoGitRepoFound,
+    Io(std::io::Error),
     GitDatabaseError(crate::git_database::GitDatabaseError),
     // Add more variants for errors specific to module 1
 }
@@ -29,6 +48,7 @@ impl std::fmt::Display for GitStatusError {
             }
             GitStatusError::NoGitRepoFound => write!(f, "GitStatus:: No git repo found"),
             GitStatusError::Git2(err) => write!(f, "GitStatus:: git error: {}", err),
+            GitStatusError::Io(err) => write!(f, "GitStatus:: io error: {}", err),
             GitStatusError::GitDatabaseError(err) => {
                 write!(f, "GitStatus:: database access error: {}", err)
             }
@@ -42,129 +62,779 @@ impl From<git2::Error> for GitStatusError {
     }
 }
 
+impl From<std::io::Error> for GitStatusError {
+    fn from(err: std::io::Error) -> GitStatusError {
+        GitStatusError::Io(err)
+    }
+}
+
 impl From<GitDatabaseError> for GitStatusError {
     fn from(err: GitDatabaseError) -> GitStatusError {
         GitStatusError::GitDatabaseError(err)
     }
 }
 
+/// Whether `path` itself is the work tree of a repo; directories inside one do not count.
 pub fn is_git_repo(path: &Path) -> bool {
-    Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("rev-parse")
-        .arg("--is-inside-work-tree")
-        .output()
-        .expect("Failed to execute git command")
-        .status
-        .success()
-}
-
-pub fn get_remote_origin(path: &Path) -> String {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("config")
-        .arg("--get")
-        .arg("remote.origin.url")
-        .output()
-        .expect("Failed to execute git command")
-        .stdout;
-
-    String::from_utf8(output).unwrap().trim().to_string()
-}
-
-pub fn get_git_status(path: &Path) -> String {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("status")
-        .arg("--porcelain")
-        .output()
-        .expect("Failed to execute git command")
-        .stdout;
-
-    String::from_utf8(output).unwrap()
-}
-
-pub fn get_unpushed_commits(path: &Path) -> String {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("rev-list")
-        .arg("--branches")
-        .arg("--not")
-        .arg("--remotes")
-        .output()
-        .expect("Failed to execute git command")
-        .stdout;
-
-    String::from_utf8(output).unwrap()
-}
-
-pub fn get_remote_updates(path: &Path) -> String {
-    Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("fetch")
-        .output()
-        .expect("Failed to execute git command");
-
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path.to_str().unwrap())
-        .arg("log")
-        .arg("..origin/master")
-        .arg("--oneline")
-        .output()
-        .expect("Failed to execute git command")
-        .stdout;
-
-    String::from_utf8(output).unwrap()
-}
-
-fn check_git_paths(path: &Path) -> Result<Vec<PathBuf>, GitStatusError> {
-    let mut git_paths = Vec::new();
-    if is_git_repo(path) {
-        git_paths.push(path.to_path_buf());
-    } else if path.is_dir() {
-        for entry in fs::read_dir(path).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if path.is_dir() {
-                match check_git_paths(&path) {
-                    Ok(mut paths) => git_paths.append(&mut paths),
-                    Err(e) => {
-                        debug!("Error processing path {}: {}", path.display(), e);
-                        continue;
-                    }
+    match Repository::open_ext(path, RepositoryOpenFlags::NO_SEARCH, &[] as &[&Path]) {
+        Ok(repo) => !repo.is_bare(),
+        Err(_) => false,
+    }
+}
+
+/// Bare repos (mirrors, backups) have no work tree, so `is_git_repo` does not see them.
+pub fn is_bare_repo(path: &Path) -> bool {
+    if !path.join("HEAD").is_file() || !path.join("objects").is_dir() {
+        return false;
+    }
+    match Repository::open_bare(path) {
+        Ok(repo) => repo.is_bare(),
+        Err(_) => false,
+    }
+}
+
+pub fn get_bare_info(repo: &Repository) -> Result<BareRepoInfo, GitStatusError> {
+    let tags = repo.tag_names(None)?.len();
+    let last_commit = match repo.head() {
+        Ok(head) => {
+            let commit = head.peel_to_commit()?;
+            DateTime::from_timestamp(commit.time().seconds(), 0)
+        }
+        Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => None,
+        Err(e) => return Err(GitStatusError::Git2(e)),
+    };
+    Ok(BareRepoInfo::new(tags, last_commit, dir_size(repo.path())))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+pub fn get_remote_origin(repo: &Repository) -> Result<String, GitStatusError> {
+    match repo.config()?.get_string("remote.origin.url") {
+        Ok(url) => Ok(url.trim().to_string()),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(String::new()),
+        Err(e) => Err(GitStatusError::Git2(e)),
+    }
+}
+
+pub fn get_git_status(repo: &Repository) -> Result<Vec<FileStatus>, GitStatusError> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(!hides_untracked(repo))
+        .include_ignored(true)
+        .recurse_ignored_dirs(false)
+        .renames_head_to_index(true);
+
+    let statuses = repo.statuses(Some(&mut options))?;
+    let entries = statuses
+        .iter()
+        .filter_map(|entry| {
+            // For staged renames `entry.path()` is the old name; report the new one.
+            let head_to_index = entry.head_to_index();
+            let path = head_to_index
+                .as_ref()
+                .and_then(|delta| delta.new_file().path().map(|p| p.display().to_string()))
+                .or_else(|| entry.path().map(|p| p.to_string()))?;
+            let orig_path = head_to_index
+                .and_then(|delta| delta.old_file().path().map(|p| p.display().to_string()))
+                .filter(|orig_path| *orig_path != path);
+            Some(file_status(path, orig_path, entry.status()))
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Whether `status.showUntrackedFiles` is `no`, as in a dotfiles repo in `~`.
+fn hides_untracked(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_string("status.showUntrackedFiles"))
+        .is_ok_and(|value| value == "no")
+}
+
+fn file_status(path: String, orig_path: Option<String>, status: Status) -> FileStatus {
+    if status.is_conflicted() {
+        return FileStatus::new(path, None, FileState::Conflicted, FileState::Conflicted);
+    }
+    if status.is_wt_new() {
+        return FileStatus::new(path, None, FileState::Unmodified, FileState::Untracked);
+    }
+    if status.is_ignored() {
+        return FileStatus::new(path, None, FileState::Unmodified, FileState::Ignored);
+    }
+    let index = if status.is_index_new() {
+        FileState::Added
+    } else if status.is_index_modified() {
+        FileState::Modified
+    } else if status.is_index_deleted() {
+        FileState::Deleted
+    } else if status.is_index_renamed() {
+        FileState::Renamed
+    } else if status.is_index_typechange() {
+        FileState::TypeChanged
+    } else {
+        FileState::Unmodified
+    };
+    let worktree = if status.is_wt_modified() {
+        FileState::Modified
+    } else if status.is_wt_deleted() {
+        FileState::Deleted
+    } else if status.is_wt_renamed() {
+        FileState::Renamed
+    } else if status.is_wt_typechange() {
+        FileState::TypeChanged
+    } else {
+        FileState::Unmodified
+    };
+    FileStatus::new(path, orig_path, index, worktree)
+}
+
+pub fn get_unpushed_commits(repo: &Repository) -> Result<String, GitStatusError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_glob("refs/heads")?;
+    revwalk.hide_glob("refs/remotes")?;
+
+    let mut output = String::new();
+    for id in revwalk {
+        output.push_str(&format!("{}\n", id?));
+    }
+    Ok(output)
+}
+
+pub fn get_branches(repo: &Repository) -> Result<Vec<BranchInfo>, GitStatusError> {
+    let mut branches = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let name = match branch.name()? {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let local = match branch.get().target() {
+            Some(oid) => oid,
+            None => continue,
+        };
+
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                branches.push(BranchInfo::new(name, None, 0, 0));
+                continue;
+            }
+            Err(e) => return Err(GitStatusError::Git2(e)),
+        };
+        let upstream_name = upstream.name()?.map(|name| name.to_string());
+        let (ahead, behind) = match upstream.get().target() {
+            Some(remote) => repo.graph_ahead_behind(local, remote)?,
+            None => (0, 0),
+        };
+        branches.push(BranchInfo::new(name, upstream_name, ahead, behind));
+    }
+    Ok(branches)
+}
+
+/// Remote used for default branch and "behind remote" checks: `origin` if present, else the first one.
+fn default_remote_name(repo: &Repository) -> Result<Option<String>, GitStatusError> {
+    let remotes = repo.remotes()?;
+    if remotes.iter().flatten().any(|name| name == "origin") {
+        return Ok(Some("origin".to_string()));
+    }
+    Ok(remotes.iter().flatten().next().map(|name| name.to_string()))
+}
+
+/// Checked out branch, or `HEAD` when detached; `None` for an unborn HEAD.
+pub fn get_head(repo: &Repository) -> Option<HistoryTip> {
+    let head = repo.head().ok()?;
+    let name = if head.is_branch() {
+        head.shorthand().unwrap_or(HISTORY_HEAD)
+    } else {
+        HISTORY_HEAD
+    };
+    Some(HistoryTip {
+        name: name.to_string(),
+        id: head.peel_to_commit().ok()?.id().to_string(),
+    })
+}
+
+/// Nearest tag reachable from HEAD, or `None` without tags or commits.
+pub fn get_latest_tag(repo: &Repository) -> Option<HistoryTip> {
+    let mut options = DescribeOptions::new();
+    options.describe_tags();
+    let name = repo
+        .describe(&options)
+        .and_then(|describe| {
+            describe.format(Some(DescribeFormatOptions::new().abbreviated_size(0)))
+        })
+        .ok()?;
+    let commit = repo.revparse_single(&name).ok()?.peel_to_commit().ok()?;
+    Some(HistoryTip {
+        name,
+        id: commit.id().to_string(),
+    })
+}
+
+/// Resolves the remote default branch (e.g. `origin/main`) from `refs/remotes/<remote>/HEAD`,
+/// falling back to `init.defaultBranch` and then to the usual branch names.
+pub fn get_default_branch(repo: &Repository) -> Result<Option<String>, GitStatusError> {
+    let remote = match default_remote_name(repo)? {
+        Some(remote) => remote,
+        None => return Ok(None),
+    };
+
+    match repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)) {
+        Ok(head) => {
+            if let Some(target) = head.symbolic_target() {
+                if let Some(branch) = target.strip_prefix("refs/remotes/") {
+                    return Ok(Some(branch.to_string()));
                 }
             }
         }
+        Err(e) if e.code() == ErrorCode::NotFound => {}
+        Err(e) => return Err(GitStatusError::Git2(e)),
     }
 
-    if git_paths.is_empty() {
-        return Err(GitStatusError::NoGitRepoFound);
+    let configured = repo.config()?.get_string("init.defaultBranch").ok();
+    let candidates = configured
+        .iter()
+        .map(|name| name.as_str())
+        .chain(["main", "master", "trunk", "develop"]);
+    for candidate in candidates {
+        let branch = format!("{}/{}", remote, candidate);
+        if repo
+            .find_reference(&format!("refs/remotes/{}", branch))
+            .is_ok()
+        {
+            return Ok(Some(branch));
+        }
+    }
+    Ok(None)
+}
+
+pub fn get_remote_updates(
+    repo: &Repository,
+    default_branch: Option<&str>,
+) -> Result<String, GitStatusError> {
+    let default_branch = match default_branch {
+        Some(branch) => branch,
+        None => return Ok(String::new()),
+    };
+    let upstream = match repo.refname_to_id(&format!("refs/remotes/{}", default_branch)) {
+        Ok(oid) => oid,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(String::new()),
+        Err(e) => return Err(GitStatusError::Git2(e)),
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(upstream)?;
+    if let Ok(head) = repo.head() {
+        if let Some(target) = head.target() {
+            revwalk.hide(target)?;
+        }
+    }
+
+    let mut output = String::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        let short_id = commit.as_object().short_id()?;
+        output.push_str(&format!(
+            "{} {}\n",
+            short_id.as_str().unwrap_or_default(),
+            commit.summary().unwrap_or_default()
+        ));
+    }
+    Ok(output)
+}
+
+/// Makes libgit2 itself give up on a remote that stops responding for `timeout`.
+///
+/// The option is process-wide in libgit2, so set it once before any repo is checked.
+pub fn set_fetch_timeout(timeout: Duration) {
+    let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    // SAFETY: called before the check starts, so no other thread uses libgit2 yet.
+    let result = unsafe {
+        git2::opts::set_server_connect_timeout_in_milliseconds(millis)
+            .and_then(|()| git2::opts::set_server_timeout_in_milliseconds(millis))
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to set fetch timeout: {}", e);
+    }
+}
+
+/// Fetches on a separate thread so a hung remote cannot hold up the check for longer than `timeout`.
+/// libgit2 times out on its own through `set_fetch_timeout`, this is only a backstop.
+fn fetch_with_timeout(path: &Path, timeout: Duration, pb: &ProgressBar) -> FetchStatus {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let repo_path = path.to_path_buf();
+    let thread_cancelled = Arc::clone(&cancelled);
+    thread::spawn(move || {
+        let result = Repository::open(&repo_path)
+            .map_err(GitStatusError::from)
+            .and_then(|repo| fetch_remote(&repo, thread_cancelled));
+        let _ = tx.send(result);
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(true)) => FetchStatus::Fetched,
+        Ok(Ok(false)) => FetchStatus::NoRemote,
+        Ok(Err(e)) => {
+            debug!("Fetch failed for {}: {}", path.display(), e);
+            FetchStatus::Failed(e.to_string())
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            // The fetch thread is left behind, but aborts at its next progress callback.
+            cancelled.store(true, Ordering::Relaxed);
+            pb.suspend(|| eprintln!("Fetch timed out after {:?} for {}", timeout, path.display()));
+            FetchStatus::TimedOut
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            FetchStatus::Failed("fetch thread exited unexpectedly".to_string())
+        }
+    }
+}
+
+/// Fetches the default remote, returning whether there was a remote to fetch.
+fn fetch_remote(repo: &Repository, cancelled: Arc<AtomicBool>) -> Result<bool, GitStatusError> {
+    let remote_name = match default_remote_name(repo)? {
+        Some(remote_name) => remote_name,
+        None => return Ok(false),
+    };
+    let mut remote = repo.find_remote(&remote_name)?;
+    let config = repo.config()?;
+
+    // Like ssh itself: the agent first, then the default key files.
+    let ssh_keys: Vec<PathBuf> = dirs::home_dir()
+        .map(|home| {
+            ["id_ed25519", "id_rsa"]
+                .iter()
+                .map(|name| home.join(".ssh").join(name))
+                .filter(|key| key.exists())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut attempts = 0;
+    let mut ssh_attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    let credentials_cancelled = Arc::clone(&cancelled);
+    callbacks.credentials(move |url, username, allowed| {
+        // libgit2 keeps asking for as long as we hand back credentials, so give up eventually.
+        attempts += 1;
+        if attempts > 3 + ssh_keys.len() || credentials_cancelled.load(Ordering::Relaxed) {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let username = username.unwrap_or("git");
+            ssh_attempts += 1;
+            match ssh_attempts {
+                1 => Cred::ssh_key_from_agent(username),
+                n => match ssh_keys.get(n - 2) {
+                    Some(key) => Cred::ssh_key(username, None, key, None),
+                    None => Err(git2::Error::from_str("authentication failed")),
+                },
+            }
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&config, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+
+    let sideband_cancelled = Arc::clone(&cancelled);
+    callbacks.sideband_progress(move |_| !sideband_cancelled.load(Ordering::Relaxed));
+    callbacks.transfer_progress(move |_| !cancelled.load(Ordering::Relaxed));
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    remote.fetch::<&str>(&[], Some(&mut fetch_options), None)?;
+    Ok(true)
+}
+
+/// Directories containing this file are skipped, together with everything below them.
+pub const IGNORE_MARKER: &str = ".gitstatusignore";
+
+/// A directory to discover repositories in, with the rules for walking it.
+#[derive(Debug, Clone)]
+pub struct ScanTarget {
+    pub path: PathBuf,
+    pub max_depth: Option<usize>,
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub skip_hidden: bool,
+    pub nested: bool,
+    pub follow_symlinks: bool,
+}
+
+impl ScanTarget {
+    /// A target for an ad-hoc path, using the global `[scan]` settings.
+    pub fn new(path: PathBuf, scan: &Scan) -> Self {
+        Self {
+            path,
+            max_depth: scan.max_depth,
+            include: Vec::new(),
+            exclude: compile_patterns(&scan.exclude),
+            skip_hidden: scan.skip_hidden,
+            nested: scan.nested,
+            follow_symlinks: scan.follow_symlinks,
+        }
+    }
+
+    /// A target for a configured root; its own settings are layered over the global ones.
+    pub fn from_root(root: &ScanRoot, scan: &Scan) -> Self {
+        let path = PathBuf::from(expand_tilde(&root.path));
+        let mut target = Self::new(fs::canonicalize(&path).unwrap_or(path), scan);
+        target.max_depth = root.max_depth.or(scan.max_depth);
+        target.include = compile_patterns(&root.include);
+        target.exclude.extend(compile_patterns(&root.exclude));
+        target
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+        if path.join(IGNORE_MARKER).exists() {
+            return true;
+        }
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(path))
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches_path(path))
+    }
+}
+
+/// Compiles glob patterns after `~` expansion, skipping invalid ones (the config validates them).
+pub fn compile_patterns(patterns: &[String]) -> Vec<Pattern> {
+    patterns
+        .iter()
+        .filter_map(|pattern| Pattern::new(&expand_tilde(pattern)).ok())
+        .collect()
+}
+
+/// Fails only when the root itself cannot be read; a readable root without repos is `Ok(vec![])`.
+fn check_git_paths(target: &ScanTarget) -> Result<Vec<PathBuf>, GitStatusError> {
+    // A missing root, e.g. an unmounted drive, must not look like a root whose repos are gone.
+    fs::read_dir(&target.path)?;
+
+    let mut git_paths = Vec::new();
+    let mut visited = HashSet::new();
+    visited.extend(dir_id(&target.path));
+
+    if target.path.join(IGNORE_MARKER).exists() {
+        debug!("Ignored by marker: {}", target.path.display());
+    } else if is_git_repo(&target.path) {
+        add_git_path(&target.path, target, &mut git_paths);
+        if target.nested {
+            discover_git_paths(&target.path, 0, target, &mut visited, &mut git_paths);
+        }
+    } else if is_bare_repo(&target.path) {
+        add_git_path(&target.path, target, &mut git_paths);
     } else {
-        Ok(git_paths)
+        discover_git_paths(&target.path, 0, target, &mut visited, &mut git_paths);
     }
+
+    Ok(git_paths)
 }
 
-pub async fn check_dir(
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+/// Identifies a directory independently of the path it was reached through.
+fn dir_id(path: &Path) -> Option<DirId> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| (metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        fs::canonicalize(path).ok()
+    }
+}
+
+fn discover_git_paths(
     path: &Path,
+    depth: usize,
+    target: &ScanTarget,
+    visited: &mut HashSet<DirId>,
+    git_paths: &mut Vec<PathBuf>,
+) {
+    if target.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        return;
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Error processing path {}: {}", path.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_symlink() {
+            if !target.follow_symlinks || !path.is_dir() {
+                continue;
+            }
+        } else if !file_type.is_dir() {
+            continue;
+        }
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if target.is_excluded(&path) {
+            debug!("Excluded from discovery: {}", path.display());
+            continue;
+        }
+        // Symlinks can lead back to a parent or to a directory reached another way.
+        match dir_id(&path) {
+            Some(id) if visited.insert(id) => {}
+            _ => {
+                debug!("Already visited: {}", path.display());
+                continue;
+            }
+        }
+        // Only directories with a `.git` entry can be the top of a work tree, so avoid
+        // opening the rest with libgit2.
+        if path.join(".git").exists() && is_git_repo(&path) {
+            add_git_path(&path, target, git_paths);
+            if !target.nested {
+                continue;
+            }
+        } else if is_bare_repo(&path) {
+            add_git_path(&path, target, git_paths);
+            continue;
+        }
+        discover_git_paths(&path, depth + 1, target, visited, git_paths);
+    }
+}
+
+/// Records a discovered repo along with its initialized submodules and linked worktrees.
+/// Paths are canonicalized so a repo reached through a symlink is keyed like any other.
+fn add_git_path(path: &Path, target: &ScanTarget, git_paths: &mut Vec<PathBuf>) {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if target.is_included(&path) {
+        git_paths.push(path.clone());
+    }
+    let repo = match Repository::open(&path) {
+        Ok(repo) => repo,
+        Err(e) => {
+            debug!("Error opening repo {}: {}", path.display(), e);
+            return;
+        }
+    };
+    for linked in linked_repo_paths(&repo) {
+        let linked = fs::canonicalize(&linked).unwrap_or(linked);
+        if target.is_included(&linked) {
+            git_paths.push(linked);
+        }
+    }
+}
+
+fn linked_repo_paths(repo: &Repository) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let (Some(workdir), Ok(submodules)) = (repo.workdir(), repo.submodules()) {
+        for submodule in submodules {
+            let path = workdir.join(submodule.path());
+            // Submodules that were never initialized have no work tree of their own.
+            if path.join(".git").exists() {
+                paths.push(path);
+            }
+        }
+    }
+    if let Ok(worktrees) = repo.worktrees() {
+        for name in worktrees.iter().flatten() {
+            if let Ok(worktree) = repo.find_worktree(name) {
+                if worktree.validate().is_ok() {
+                    paths.push(worktree.path().to_path_buf());
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// Works out how a repo relates to others: a linked worktree, a submodule, or nested in
+/// another repo's work tree.
+pub fn get_repo_kind(repo: &Repository) -> (RepoKind, Option<String>) {
+    let workdir_of = |path: &Path| {
+        path.to_str()
+            .unwrap_or_default()
+            .trim_end_matches('/')
+            .trim_end_matches("/.git")
+            .to_string()
+    };
+
+    if repo.is_worktree() {
+        // A linked worktree's git dir points back at the main repo's through `commondir`.
+        let common_dir = fs::read_to_string(repo.path().join("commondir"))
+            .ok()
+            .and_then(|common_dir| fs::canonicalize(repo.path().join(common_dir.trim())).ok());
+        return (RepoKind::Worktree, common_dir.map(|dir| workdir_of(&dir)));
+    }
+
+    // Submodule git dirs live under the superproject's `.git/modules/`.
+    let git_dir = repo.path().to_str().unwrap_or_default();
+    if let Some((superproject, _)) = git_dir.split_once("/.git/modules/") {
+        return (RepoKind::Submodule, Some(superproject.to_string()));
+    }
+
+    let parent = repo.workdir().and_then(|workdir| {
+        let outer = Repository::discover(workdir.parent()?).ok()?;
+        let outer_workdir = outer.workdir()?;
+        let relative = workdir.strip_prefix(outer_workdir).ok()?;
+        contains_path(&outer, relative).then(|| workdir_of(outer_workdir))
+    });
+    match parent {
+        Some(parent) => (RepoKind::Nested, Some(parent)),
+        None => (RepoKind::Standalone, None),
+    }
+}
+
+/// Whether a repo's work tree really holds `relative` rather than just enclosing it: the
+/// path is not ignored, and tracked unless untracked files are hidden altogether as in a
+/// dotfiles repo in `~`.
+fn contains_path(repo: &Repository, relative: &Path) -> bool {
+    if repo.is_path_ignored(relative).unwrap_or(false) {
+        return false;
+    }
+    !hides_untracked(repo)
+        || repo
+            .index()
+            .is_ok_and(|index| index.get_path(relative, 0).is_some())
+}
+
+/// Oldest commit without parents reachable from HEAD, `None` for an unborn HEAD.
+pub fn get_root_commit(repo: &Repository) -> Result<Option<String>, GitStatusError> {
+    let head = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(head) => head.id(),
+        Err(_) => return Ok(None),
+    };
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    revwalk.push(head)?;
+    match revwalk.next() {
+        Some(id) => Ok(Some(id?.to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Takes over the record of a stored repo with the same identity whose path is gone or
+/// resolves to `repo`, and returns the path it was moved from.
+fn find_moved_repo(
+    repo: &Path,
+    root_commit: &str,
+    origin_url: &str,
+    gitdb: &GitDatabase,
+) -> Result<Option<String>, GitStatusError> {
+    let path = repo.display().to_string();
+    for candidate in gitdb.find_by_identity(root_commit, origin_url, &path)? {
+        // Stored under a symlinked path before paths were canonicalized.
+        let symlinked = fs::canonicalize(&candidate).is_ok_and(|canonical| canonical == repo);
+        if !symlinked && Repository::open(&candidate).is_ok() {
+            // Still there, a second clone rather than a move.
+            continue;
+        }
+        if gitdb.rename_repo(&candidate, &path)? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Stored repos whose path is gone or no longer opens as a repo, limited to those below
+/// `roots` unless it is empty.
+pub fn find_stale_repos(
+    gitdb: &GitDatabase,
+    roots: &[PathBuf],
+) -> Result<Vec<String>, GitStatusError> {
+    let stale = gitdb
+        .repo_paths()?
+        .into_iter()
+        .filter(|path| {
+            let path = Path::new(path);
+            roots.is_empty() || roots.iter().any(|root| path.starts_with(root))
+        })
+        .filter(|path| is_stale_repo(Path::new(path)))
+        .collect();
+    Ok(stale)
+}
+
+/// Only a missing path or a directory that is not a repo anymore counts as stale; other
+/// failures such as permission errors or locks may be transient.
+fn is_stale_repo(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        Err(e) => e.kind() == std::io::ErrorKind::NotFound,
+        Ok(_) => matches!(
+            Repository::open(path),
+            Err(ref e) if e.code() == ErrorCode::NotFound
+        ),
+    }
+}
+
+/// Checks all repos found below `targets`. Also returns the roots that were scanned
+/// successfully, the only ones where missing repos can safely be pruned.
+pub async fn check_dir(
+    targets: &[ScanTarget],
     detail_level: &u8,
+    fetch: &Fetch,
+    history: &History,
+    jobs: usize,
     gitdb: &GitDatabase,
-) -> Result<Vec<GitRepoInfo>, GitStatusError> {
+) -> Result<(Vec<GitRepoInfo>, Vec<PathBuf>), GitStatusError> {
     let mut repos = Vec::new();
 
-    debug!("Checking path: {:?}", &path);
-    let git_paths = match check_git_paths(path) {
-        Ok(paths) => paths,
-        Err(e) => {
-            eprintln!("Error processing path {}: {}", path.display(), e);
-            return Err(e);
+    let mut git_paths = Vec::new();
+    let mut scanned_roots = Vec::new();
+    for target in targets {
+        debug!("Checking path: {:?}", &target.path);
+        match check_git_paths(target) {
+            Ok(mut paths) => {
+                git_paths.append(&mut paths);
+                // Repo keys are canonical, so compare against the canonical root.
+                scanned_roots.push(fs::canonicalize(&target.path).unwrap_or(target.path.clone()));
+            }
+            Err(e) => eprintln!("Error processing path {}: {}", target.path.display(), e),
         }
-    };
+    }
+    // Configured roots may overlap, check each repo only once.
+    git_paths.sort();
+    git_paths.dedup();
+    if scanned_roots.is_empty() {
+        return Err(GitStatusError::NoGitRepoFound);
+    }
+    // Still report the roots, so records of their last repos can be pruned.
+    if git_paths.is_empty() {
+        return Ok((repos, scanned_roots));
+    }
 
     let pb = ProgressBar::new(git_paths.len() as u64);
     pb.set_style(
@@ -175,83 +845,175 @@ pub async fn check_dir(
             .expect("Failed to create progress bar style"),
     );
     pb.tick(); // Redraw the progress bar immediately
-               //
+
+    // git2 and tokei are blocking, so run them on the blocking pool, at most `jobs` repos at once.
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
     let tasks: Vec<_> = git_paths
         .into_iter()
         .map(|repo| {
             let pb = pb.clone();
             let detail_level = *detail_level;
+            let fetch = fetch.clone();
+            let history = history.clone();
             let gitdb = gitdb.clone();
-            tokio::spawn(async move {
-                let start = std::time::Instant::now();
-
-                let status = get_git_status(&repo);
-                let unpushed = get_unpushed_commits(&repo);
-                let updates = get_remote_updates(&repo);
-                let origin_url = get_remote_origin(&repo);
-                let languages = match get_languages_summary(&repo, &detail_level, &gitdb) {
-                    Ok(languages) => languages,
-                    Err(e) => {
-                        println!(
-                            "Repo not existed in DB return an empty Languages. Error::{}",
-                            e
-                        );
-                        Languages::new()
-                    }
-                };
-                let commits_list = match get_commits_history(&repo, &detail_level, &gitdb) {
-                    Ok(commits) => commits,
-                    Err(e) => {
-                        debug!("Repo not existed in DB return an empty Vec. Error::{}", e);
-                        Vec::new()
-                    }
-                };
-
-                let repo_info = GitRepoInfo::new(
-                    repo.to_str().unwrap().to_string(),
-                    Some(origin_url),
-                    status,
-                    unpushed,
-                    updates,
-                    Some(Version::parse(env!("CARGO_PKG_VERSION")).unwrap()),
-                    Some(commits_list),
-                    Some(languages),
-                );
+            let semaphore = Arc::clone(&semaphore);
+            async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("Check semaphore closed");
+                task::spawn_blocking(move || {
+                    let start = std::time::Instant::now();
+                    let result = check_repo(&repo, &detail_level, &fetch, &history, &gitdb, &pb);
+                    pb.inc(1);
 
-                pb.inc(1);
-
-                let duration = start.elapsed();
-                Ok::<_, GitStatusError>((repo_info, duration))
-            })
+                    match result {
+                        Ok(repo_info) => Ok((repo_info, start.elapsed())),
+                        Err(e) => {
+                            pb.suspend(|| {
+                                eprintln!("Failed to check repo {}: {}", repo.display(), e)
+                            });
+                            Err(e)
+                        }
+                    }
+                })
+                .await
+            }
         })
         .collect();
 
-    let results: Result<Vec<_>, _> = futures::future::try_join_all(tasks).await;
+    let results = futures::future::join_all(tasks).await;
 
     let mut durations = Vec::new();
 
-    match results {
-        Ok(repo_infos) => {
-            for repo_info in repo_infos {
-                let (repo_info, duration) = repo_info?;
+    // Failures are already reported per repo, keep going with the rest.
+    for result in results {
+        match result {
+            Ok(Ok((repo_info, duration))) => {
                 durations.push((repo_info.path.clone(), duration));
                 repos.push(repo_info);
             }
+            Ok(Err(_)) => {}
+            Err(e) => pb.suspend(|| eprintln!("Error running task: {}", e)),
         }
-        Err(e) => eprintln!("Error spawning task: {}", e),
     }
 
     pb.finish_with_message("done");
 
     // Sort durations by descending order and take the first 3
-    durations.sort_by(|a, b| b.1.cmp(&a.1));
+    durations.sort_by_key(|b| std::cmp::Reverse(b.1));
     let longest_durations = durations.into_iter().take(3);
 
     for (path, duration) in longest_durations {
         debug!("Path: {}, Duration: {:?}", path, duration);
     }
 
-    Ok(repos)
+    Ok((repos, scanned_roots))
+}
+
+fn check_repo(
+    repo: &Path,
+    detail_level: &u8,
+    fetch: &Fetch,
+    history: &History,
+    gitdb: &GitDatabase,
+    pb: &ProgressBar,
+) -> Result<GitRepoInfo, GitStatusError> {
+    let git_repo = Repository::open(repo)?;
+    let (kind, parent) = get_repo_kind(&git_repo);
+    let origin_url = get_remote_origin(&git_repo)?;
+    let mut previous = gitdb.get_repo_details(repo.to_path_buf()).ok();
+    let root_commit = match previous
+        .as_ref()
+        .and_then(|previous| previous.root_commit.as_deref())
+    {
+        // The root never changes, unless the history was rewritten from scratch.
+        Some(root) if Oid::from_str(root).is_ok_and(|id| git_repo.find_commit(id).is_ok()) => {
+            Some(root.to_string())
+        }
+        _ => get_root_commit(&git_repo)?,
+    };
+    if previous.is_none() {
+        if let Some(ref root_commit) = root_commit {
+            if let Some(from) = find_moved_repo(repo, root_commit, &origin_url, gitdb)? {
+                // Print above the progress bar instead of through it.
+                pb.suspend(|| println!("Repo moved: {} -> {}", from, repo.display()));
+                previous = gitdb.get_repo_details(repo.to_path_buf()).ok();
+            }
+        }
+    }
+    let mut last_fetch = previous.as_ref().and_then(|previous| previous.last_fetch);
+    let fetch_status = if fetch.should_fetch(repo, last_fetch) {
+        fetch_with_timeout(repo, fetch.timeout, pb)
+    } else {
+        debug!("Skipping fetch for {}", repo.display());
+        FetchStatus::Skipped
+    };
+    if fetch_status == FetchStatus::Fetched {
+        last_fetch = Some(Utc::now());
+    }
+    // Work tree fields do not apply to bare repos, they get the bare specific ones instead.
+    let bare = if git_repo.is_bare() {
+        Some(get_bare_info(&git_repo)?)
+    } else {
+        None
+    };
+    let (status, unpushed) = match bare {
+        // Mirrors keep their branches under refs/heads without remote tracking refs.
+        Some(_) => (Vec::new(), String::new()),
+        None => (get_git_status(&git_repo)?, get_unpushed_commits(&git_repo)?),
+    };
+    let default_branch = get_default_branch(&git_repo)?;
+    let updates = get_remote_updates(&git_repo, default_branch.as_deref())?;
+    let branches = get_branches(&git_repo)?;
+    let latest_tag = get_latest_tag(&git_repo);
+    let head = get_head(&git_repo);
+    // A bare repo has no work tree to count, only git internals.
+    let languages = match bare {
+        Some(_) => Languages::new(),
+        None => match get_languages_summary(repo, detail_level, gitdb) {
+            Ok(languages) => languages,
+            Err(e) => {
+                pb.suspend(|| {
+                    println!(
+                        "Repo not existed in DB return an empty Languages. Error::{}",
+                        e
+                    )
+                });
+                Languages::new()
+            }
+        },
+    };
+    let (commits_list, history_tips) =
+        match get_commits_history(&git_repo, detail_level, history, previous.as_ref()) {
+            Ok(history) => history,
+            Err(e) => {
+                debug!("Repo not existed in DB return an empty Vec. Error::{}", e);
+                (Vec::new(), Vec::new())
+            }
+        };
+
+    Ok(GitRepoInfo::new(
+        repo.to_str().unwrap().to_string(),
+        Some(origin_url),
+        root_commit,
+        status,
+        unpushed,
+        updates,
+        branches,
+        default_branch,
+        head,
+        last_fetch,
+        fetch_status,
+        kind,
+        parent,
+        bare,
+        Some(Version::parse(env!("CARGO_PKG_VERSION")).unwrap()),
+        Some(commits_list),
+        history_tips,
+        latest_tag,
+        Some(languages),
+    ))
 }
 
 fn get_languages_summary(
@@ -260,7 +1022,7 @@ fn get_languages_summary(
     gitdb: &GitDatabase,
 ) -> Result<Languages, GitStatusError> {
     let languages = match detail_level {
-        &0 => match gitdb.get_repo_details(path.to_path_buf()) {
+        0 => match gitdb.get_repo_details(path.to_path_buf()) {
             Ok(repo) => {
                 let required_version = Version::parse("0.6.0").unwrap();
                 if repo.app_version >= required_version {
@@ -277,7 +1039,7 @@ fn get_languages_summary(
             }
             Err(e) => return Err(GitStatusError::GitDatabaseError(e)),
         },
-        &1 => {
+        1 => {
             let config = TokeiCfg::default();
             let mut languages = Languages::new();
             languages.get_statistics(&[path], &[], &config);
@@ -289,67 +1051,972 @@ fn get_languages_summary(
     Ok(languages)
 }
 
+/// Returns the commit history (newest first) and the history tips it was collected at.
+///
+/// At detail level 1 only commits that are not reachable from the stored tips are walked and
+/// diffed, and the branches of stored commits are only updated for refs whose tip moved.
+/// Commits that are no longer reachable from any tip (force-push, rebase, reset, deleted
+/// branch) are removed from the stored list.
 fn get_commits_history(
-    path: &Path,
+    repo: &Repository,
     detail_level: &u8,
-    gitdb: &GitDatabase,
-) -> Result<Vec<GitCommit>, GitStatusError> {
-    let mut commits_list = Vec::new();
+    history: &History,
+    previous: Option<&GitRepoInfo>,
+) -> Result<(Vec<GitCommit>, Vec<HistoryTip>), GitStatusError> {
     match detail_level {
-        &0 => match gitdb.get_repo_details(path.to_path_buf()) {
-            Ok(repo) => {
-                return Ok(repo
-                    .commits
-                    .expect("GitStatus:: failed to get commits history form SledDB"))
-            }
-            Err(e) => return Err(GitStatusError::GitDatabaseError(e)),
+        0 => match previous {
+            Some(previous) => Ok((
+                previous.commits.clone().unwrap_or_default(),
+                previous.history_tips.clone(),
+            )),
+            None => Err(GitStatusError::GitDatabaseError(
+                GitDatabaseError::KeyNotExist,
+            )),
         },
-        &1 => {
-            let repo = Repository::open(path)?;
-            let mut revwalk = repo.revwalk()?;
-            revwalk.push_head()?;
-            for id in revwalk {
-                let id = id?;
-                let commit = repo.find_commit(id)?;
-                let diff = if commit.parent_count() > 0 {
-                    let parent = commit.parent(0)?;
-                    repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?), None)?
-                } else {
-                    let tree = commit.tree()?;
-                    let empty_tree = repo.treebuilder(None)?.write()?;
-                    let empty_tree = repo.find_tree(empty_tree)?;
-                    repo.diff_tree_to_tree(Some(&empty_tree), Some(&tree), None)?
-                };
-                let stats = diff.stats()?;
-                let author_email = commit.author().email().unwrap_or("").to_string();
-                let message = commit.message().unwrap_or("").to_string();
-                let git_commits = GitCommit::new(
-                    id.to_string(),
-                    author_email,
-                    SerializableTime(commit.time()),
-                    message,
-                    stats.files_changed(),
-                    stats.insertions(),
-                    stats.deletions(),
+        1 => {
+            let tips = get_history_tips(repo, history)?;
+            // Without the stored tips there is nothing to build on.
+            let stored = previous.and_then(|previous| {
+                let commits = previous.commits.as_ref()?;
+                let old_tips = previous
+                    .history_tips
+                    .iter()
+                    .map(|tip| {
+                        let id = Oid::from_str(&tip.id).ok()?;
+                        repo.find_commit(id).ok()?;
+                        Some((tip.name.as_str(), id))
+                    })
+                    .collect::<Option<HashMap<&str, Oid>>>()?;
+                (!old_tips.is_empty()).then_some((previous, commits, old_tips))
+            });
+            let (stored_commits, old_tips) = match stored {
+                Some((previous, commits, _)) if previous.history_tips == tips => {
+                    return Ok((commits.clone(), tips));
+                }
+                Some((_, commits, old_tips)) => (commits.as_slice(), old_tips),
+                None => {
+                    debug!("Collecting commit history from scratch");
+                    (&[][..], HashMap::new())
+                }
+            };
+
+            let new_tips = tips
+                .iter()
+                .map(|tip| Ok((tip.name.as_str(), Oid::from_str(&tip.id)?)))
+                .collect::<Result<Vec<_>, GitStatusError>>()?;
+            // Membership only changes along the path a ref moved, or everywhere for new refs.
+            let mut gained: HashMap<Oid, Vec<&str>> = HashMap::new();
+            let mut lost: HashSet<(Oid, &str)> = HashSet::new();
+            for &(name, id) in &new_tips {
+                let old = old_tips.get(name).copied();
+                if old == Some(id) {
+                    continue;
+                }
+                for commit in reachable(repo, &[id], old.as_slice())? {
+                    gained.entry(commit).or_default().push(name);
+                }
+                if let Some(old) = old {
+                    for commit in reachable(repo, &[old], &[id])? {
+                        lost.insert((commit, name));
+                    }
+                }
+            }
+
+            let new_ids: Vec<Oid> = new_tips.iter().map(|&(_, id)| id).collect();
+            let old_ids: Vec<Oid> = old_tips.values().copied().collect();
+            let dropped: HashSet<Oid> = reachable(repo, &old_ids, &new_ids)?.into_iter().collect();
+            if !dropped.is_empty() {
+                debug!(
+                    "History rewritten or refs removed, dropping {} stored commits",
+                    dropped.len()
                 );
-                commits_list.push(git_commits)
+            }
+
+            let mut commits_list = walk_commits(repo, &new_ids, &old_ids, &gained, history)?;
+            for commit in stored_commits {
+                let Ok(id) = Oid::from_str(&commit.hash) else {
+                    continue;
+                };
+                if dropped.contains(&id) {
+                    continue;
+                }
+                // Refs that are not configured anymore fall out here as well.
+                let branches = new_tips
+                    .iter()
+                    .map(|&(name, _)| name)
+                    .filter(|&name| {
+                        gained.get(&id).is_some_and(|names| names.contains(&name))
+                            || (commit.branches.iter().any(|branch| branch == name)
+                                && !lost.contains(&(id, name)))
+                    })
+                    .map(String::from)
+                    .collect();
+                commits_list.push(GitCommit {
+                    branches,
+                    ..commit.clone()
+                });
+            }
+            Ok((commits_list, tips))
+        }
+        _ => Err(GitStatusError::InvalidDetailLevel),
+    }
+}
+
+/// Commits reachable from `from` but not from `hide`.
+fn reachable(repo: &Repository, from: &[Oid], hide: &[Oid]) -> Result<Vec<Oid>, GitStatusError> {
+    let mut revwalk = repo.revwalk()?;
+    for id in from {
+        revwalk.push(*id)?;
+    }
+    for id in hide {
+        revwalk.hide(*id)?;
+    }
+    Ok(revwalk.collect::<Result<Vec<Oid>, _>>()?)
+}
+
+/// Resolves the configured history refs to the commits they point at, once per ref name.
+fn get_history_tips(
+    repo: &Repository,
+    history: &History,
+) -> Result<Vec<HistoryTip>, GitStatusError> {
+    let mut tips: Vec<HistoryTip> = Vec::new();
+    // Symbolic refs such as origin/HEAD are skipped, their target is walked anyway.
+    let mut add_tip = |name: &str, reference: &Reference| {
+        if reference.symbolic_target().is_some() || tips.iter().any(|tip| tip.name == name) {
+            return;
+        }
+        if let Ok(commit) = reference.peel_to_commit() {
+            tips.push(HistoryTip {
+                name: name.to_string(),
+                id: commit.id().to_string(),
+            });
+        }
+    };
+    for spec in &history.refs {
+        match spec.as_str() {
+            // An unborn HEAD has no history yet.
+            HISTORY_HEAD => {
+                if let Ok(head) = repo.head() {
+                    let name = if head.is_branch() {
+                        head.shorthand().unwrap_or(HISTORY_HEAD)
+                    } else {
+                        HISTORY_HEAD
+                    };
+                    add_tip(name, &head);
+                }
+            }
+            HISTORY_LOCAL | HISTORY_REMOTE => {
+                let branch_type = match spec.as_str() {
+                    HISTORY_LOCAL => BranchType::Local,
+                    _ => BranchType::Remote,
+                };
+                for branch in repo.branches(Some(branch_type))? {
+                    let (branch, _) = branch?;
+                    let reference = branch.get();
+                    if let Some(name) = reference.shorthand() {
+                        add_tip(name, reference);
+                    }
+                }
+            }
+            pattern => {
+                // Patterns are checked when the config is loaded.
+                let Ok(pattern) = Pattern::new(pattern) else {
+                    continue;
+                };
+                for reference in repo.references()? {
+                    let reference = reference?;
+                    let (Some(name), Some(shorthand)) = (reference.name(), reference.shorthand())
+                    else {
+                        continue;
+                    };
+                    if pattern.matches(name) || pattern.matches(shorthand) {
+                        add_tip(shorthand, &reference);
+                    }
+                }
             }
         }
-        _ => return Err(GitStatusError::InvalidDetailLevel),
+    }
+    Ok(tips)
+}
+
+/// Walks and diffs the commits reachable from `from` but not from `hide`, newest first.
+fn walk_commits(
+    repo: &Repository,
+    from: &[Oid],
+    hide: &[Oid],
+    branches: &HashMap<Oid, Vec<&str>>,
+    history: &History,
+) -> Result<Vec<GitCommit>, GitStatusError> {
+    let mut commits_list = Vec::new();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    for id in from {
+        revwalk.push(*id)?;
+    }
+    for id in hide {
+        revwalk.hide(*id)?;
+    }
+    for id in revwalk {
+        let id = id?;
+        let contained_in = branches
+            .get(&id)
+            .map(|names| names.iter().map(|name| name.to_string()).collect())
+            .unwrap_or_default();
+        let commit = repo.find_commit(id)?;
+        let files = match commit_diff(repo, &commit, history)? {
+            Some(mut diff) => {
+                let mut find = DiffFindOptions::new();
+                find.renames(true)
+                    .rename_threshold(history.rename_threshold)
+                    .copies(history.detect_copies)
+                    .copy_threshold(history.rename_threshold);
+                diff.find_similar(Some(&mut find))?;
+                file_changes(&diff)?
+            }
+            None => Vec::new(),
+        };
+        // Binary files have no lines, keep them out of the text counts.
+        let text = files.iter().filter(|file| !file.binary);
+        let insertions = text.clone().map(|file| file.insertions).sum();
+        let deletions = text.map(|file| file.deletions).sum();
+        let author = commit.author();
+        let committer = commit.committer();
+        let message = commit.message().unwrap_or("").to_string();
+        let git_commits = GitCommit::new(
+            id.to_string(),
+            author.name().unwrap_or("").to_string(),
+            author.email().unwrap_or("").to_string(),
+            SerializableTime(author.when()),
+            committer.name().unwrap_or("").to_string(),
+            committer.email().unwrap_or("").to_string(),
+            SerializableTime(committer.when()),
+            commit.parent_ids().map(|id| id.to_string()).collect(),
+            message,
+            files.len(),
+            insertions,
+            deletions,
+            files,
+            contained_in,
+        );
+        commits_list.push(git_commits)
     }
     Ok(commits_list)
 }
 
+/// Diff a commit introduced, or `None` for merges when merges are excluded from stats.
+fn commit_diff<'a>(
+    repo: &'a Repository,
+    commit: &Commit,
+    history: &History,
+) -> Result<Option<Diff<'a>>, GitStatusError> {
+    let tree = commit.tree()?;
+    let base = match commit.parent_count() {
+        0 => {
+            let empty_tree = repo.treebuilder(None)?.write()?;
+            repo.find_tree(empty_tree)?
+        }
+        1 => commit.parent(0)?.tree()?,
+        _ => match history.merges {
+            MergeStats::Exclude => return Ok(None),
+            MergeStats::FirstParent => commit.parent(0)?.tree()?,
+            MergeStats::AutoMerge => match auto_merged_tree(repo, commit)? {
+                Some(tree) => tree,
+                None => return Ok(None),
+            },
+        },
+    };
+    Ok(Some(repo.diff_tree_to_tree(
+        Some(&base),
+        Some(&tree),
+        None,
+    )?))
+}
+
+/// Stage bits of `IndexEntry::flags`, zero for resolved entries.
+const INDEX_STAGE_MASK: u16 = 0x3000;
+
+/// Tree git would have produced for a two-parent merge before any manual edits.
+/// Conflicted paths take the first parent's side, so their resolution counts as the merge's own change.
+fn auto_merged_tree<'a>(
+    repo: &'a Repository,
+    commit: &Commit,
+) -> Result<Option<Tree<'a>>, GitStatusError> {
+    // Octopus merges refuse to run with conflicts, so they introduce nothing of their own.
+    if commit.parent_count() != 2 {
+        return Ok(None);
+    }
+    let mut index = repo.merge_commits(&commit.parent(0)?, &commit.parent(1)?, None)?;
+    if index.has_conflicts() {
+        let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+        for conflict in conflicts {
+            let entry = conflict
+                .our
+                .as_ref()
+                .or(conflict.their.as_ref())
+                .or(conflict.ancestor.as_ref());
+            if let Some(entry) = entry {
+                let path = String::from_utf8_lossy(&entry.path).to_string();
+                index.remove_path(Path::new(&path))?;
+            }
+            if let Some(mut ours) = conflict.our {
+                ours.flags &= !INDEX_STAGE_MASK;
+                index.add(&ours)?;
+            }
+        }
+    }
+    let tree = index.write_tree_to(repo)?;
+    Ok(Some(repo.find_tree(tree)?))
+}
+
+fn file_changes(diff: &Diff) -> Result<Vec<FileChange>, GitStatusError> {
+    let mut files = Vec::new();
+    for (idx, delta) in diff.deltas().enumerate() {
+        let kind = match delta.status() {
+            Delta::Added | Delta::Untracked => ChangeKind::Added,
+            Delta::Deleted => ChangeKind::Deleted,
+            Delta::Renamed => ChangeKind::Renamed,
+            Delta::Copied => ChangeKind::Copied,
+            Delta::Typechange => ChangeKind::TypeChanged,
+            _ => ChangeKind::Modified,
+        };
+        let new_path = delta.new_file().path();
+        let old_path = delta.old_file().path();
+        let path = match new_path.or(old_path) {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => continue,
+        };
+        let old_path = match kind {
+            ChangeKind::Renamed | ChangeKind::Copied => {
+                old_path.map(|path| path.to_string_lossy().to_string())
+            }
+            _ => None,
+        };
+        // Loading the patch also settles whether either side is binary.
+        let patch = Patch::from_diff(diff, idx)?;
+        let binary = match patch {
+            Some(ref patch) => patch.delta().flags().is_binary(),
+            None => delta.flags().is_binary(),
+        };
+        let (insertions, deletions) = match patch {
+            Some(ref patch) if !binary => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                (insertions, deletions)
+            }
+            _ => (0, 0),
+        };
+        files.push(FileChange {
+            path,
+            old_path,
+            kind,
+            binary,
+            insertions,
+            deletions,
+        });
+    }
+    Ok(files)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::FetchPolicy;
+    #[cfg(feature = "dev")]
     use env_logger;
-    use std::path::Path;
+    use git2::Signature;
+    use tempfile::TempDir;
 
+    #[cfg(feature = "dev")]
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
     }
 
+    /// Commits `content` to `name` on top of HEAD.
+    fn commit_file(repo: &Repository, name: &str, content: &str, message: &str) -> Oid {
+        fs::write(repo.workdir().unwrap().join(name), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Tester", "tester@example.com").unwrap();
+        let parent = repo.head().and_then(|head| head.peel_to_commit()).ok();
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    /// A repo at `path` with a single commit.
+    fn init_repo(path: &Path) -> Repository {
+        fs::create_dir_all(path).unwrap();
+        let repo = Repository::init(path).unwrap();
+        commit_file(&repo, "README.md", "readme\n", "Initial commit");
+        repo
+    }
+
+    fn scan_root() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        (dir, root)
+    }
+
+    fn open_db(dir: &TempDir) -> GitDatabase {
+        GitDatabase::new(&dir.path().join("db")).unwrap()
+    }
+
+    /// Checks `roots` and saves the results like `check` does, returning the scanned roots.
+    async fn check_and_save(
+        roots: &[&Path],
+        history: &History,
+        gitdb: &GitDatabase,
+    ) -> Vec<PathBuf> {
+        let targets: Vec<ScanTarget> = roots
+            .iter()
+            .map(|root| ScanTarget::new(root.to_path_buf(), &Scan::default()))
+            .collect();
+        let fetch = Fetch {
+            policy: FetchPolicy::Never,
+            ..Fetch::default()
+        };
+        let (repos, scanned) = check_dir(&targets, &1, &fetch, history, 1, gitdb)
+            .await
+            .unwrap();
+        for repo in repos {
+            gitdb.save_to_db(&repo).unwrap();
+        }
+        scanned
+    }
+
+    fn discover(target: &ScanTarget) -> Vec<PathBuf> {
+        let mut paths = check_git_paths(target).unwrap_or_default();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_discovery_rules() {
+        let (_dir, root) = scan_root();
+        init_repo(&root.join("app"));
+        init_repo(&root.join("node_modules/dep"));
+        init_repo(&root.join(".cache/tool"));
+        init_repo(&root.join("archive/old"));
+        fs::write(root.join("archive").join(IGNORE_MARKER), "").unwrap();
+        init_repo(&root.join("deep/er/lib"));
+        init_repo(&root.join("app/vendor/inner"));
+
+        let mut target = ScanTarget::new(root.clone(), &Scan::default());
+        assert_eq!(
+            discover(&target),
+            vec![root.join("app"), root.join("deep/er/lib")]
+        );
+
+        target.max_depth = Some(2);
+        assert_eq!(discover(&target), vec![root.join("app")]);
+
+        target.max_depth = None;
+        target.skip_hidden = false;
+        target.nested = true;
+        target.exclude = compile_patterns(&["**/deep".to_string()]);
+        assert_eq!(
+            discover(&target),
+            vec![
+                root.join(".cache/tool"),
+                root.join("app"),
+                root.join("app/vendor/inner"),
+                root.join("node_modules/dep"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_untracked_files() {
+        let (_dir, root) = scan_root();
+        let repo = init_repo(&root.join("home"));
+        fs::write(root.join("home/.bashrc"), "alias ll='ls -l'\n").unwrap();
+        let paths = |repo: &Repository| -> Vec<String> {
+            get_git_status(repo)
+                .unwrap()
+                .into_iter()
+                .map(|status| status.path)
+                .collect()
+        };
+        assert_eq!(paths(&repo), vec![".bashrc".to_string()]);
+
+        repo.config()
+            .unwrap()
+            .set_str("status.showUntrackedFiles", "no")
+            .unwrap();
+        assert!(paths(&repo).is_empty());
+    }
+
+    #[test]
+    fn test_default_branch() {
+        let (_dir, root) = scan_root();
+        let origin = init_repo(&root.join("origin"));
+        assert_eq!(get_default_branch(&origin).unwrap(), None);
+        let head = origin.head().unwrap().peel_to_commit().unwrap();
+        origin.branch("trunk", &head, false).unwrap();
+        origin.set_head("refs/heads/trunk").unwrap();
+
+        // Taken from the remote HEAD recorded by the clone.
+        let clone =
+            Repository::clone(root.join("origin").to_str().unwrap(), root.join("clone")).unwrap();
+        assert_eq!(
+            get_default_branch(&clone).unwrap().as_deref(),
+            Some("origin/trunk")
+        );
+
+        // Without it, `init.defaultBranch` goes before the usual names such as `master`.
+        clone
+            .find_reference("refs/remotes/origin/HEAD")
+            .unwrap()
+            .delete()
+            .unwrap();
+        clone
+            .config()
+            .unwrap()
+            .set_str("init.defaultBranch", "trunk")
+            .unwrap();
+        assert_eq!(
+            get_default_branch(&clone).unwrap().as_deref(),
+            Some("origin/trunk")
+        );
+    }
+
+    #[test]
+    fn test_repo_kind() {
+        let (_dir, root) = scan_root();
+        let app = init_repo(&root.join("app"));
+        let path = |path: PathBuf| Some(path.display().to_string());
+        assert_eq!(get_repo_kind(&app), (RepoKind::Standalone, None));
+
+        app.worktree("feature", &root.join("feature"), None)
+            .unwrap();
+        let worktree = Repository::open(root.join("feature")).unwrap();
+        assert_eq!(
+            get_repo_kind(&worktree),
+            (RepoKind::Worktree, path(root.join("app")))
+        );
+
+        let nested = init_repo(&root.join("app/vendor/lib"));
+        assert_eq!(
+            get_repo_kind(&nested),
+            (RepoKind::Nested, path(root.join("app")))
+        );
+    }
+
+    #[test]
+    fn test_bare_repos() {
+        let (_dir, root) = scan_root();
+        let empty = Repository::init_bare(root.join("empty.git")).unwrap();
+        assert!(is_bare_repo(&root.join("empty.git")));
+        assert!(!is_git_repo(&root.join("empty.git")));
+        let info = get_bare_info(&empty).unwrap();
+        assert_eq!((info.tags, info.last_commit), (0, None));
+        assert!(info.size_bytes > 0);
+
+        let app = init_repo(&root.join("app"));
+        assert!(!is_bare_repo(&root.join("app")));
+        let head = app.head().unwrap().peel_to_commit().unwrap();
+        app.tag_lightweight("v1.0.0", head.as_object(), false)
+            .unwrap();
+        let mirror = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(root.join("app").to_str().unwrap(), &root.join("mirror.git"))
+            .unwrap();
+        assert!(is_bare_repo(&root.join("mirror.git")));
+        let info = get_bare_info(&mirror).unwrap();
+        assert_eq!(info.tags, 1);
+        assert_eq!(
+            info.last_commit,
+            DateTime::from_timestamp(head.time().seconds(), 0)
+        );
+    }
+
+    #[test]
+    fn test_discovery_inside_work_tree() {
+        let (_dir, root) = scan_root();
+        init_repo(&root.join("app"));
+        fs::create_dir_all(root.join("app/src")).unwrap();
+
+        assert!(is_git_repo(&root.join("app")));
+        assert!(!is_git_repo(&root.join("app/src")));
+        let target = ScanTarget::new(root.join("app/src"), &Scan::default());
+        assert!(check_git_paths(&target).unwrap().is_empty());
+        let target = ScanTarget::new(root.join("missing"), &Scan::default());
+        assert!(matches!(
+            check_git_paths(&target),
+            Err(GitStatusError::Io(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discovery_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let (_dir, root) = scan_root();
+        init_repo(&root.join("repos/app"));
+        symlink(&root, root.join("repos/loop")).unwrap();
+        symlink(root.join("repos/app"), root.join("app-link")).unwrap();
+        let (_outside, outside) = scan_root();
+        init_repo(&outside.join("lib"));
+        symlink(outside.join("lib"), root.join("lib-link")).unwrap();
+
+        let mut target = ScanTarget::new(root.clone(), &Scan::default());
+        assert_eq!(discover(&target), vec![root.join("repos/app")]);
+
+        // Followed links are keyed by their target, and the loop back to the root ends.
+        target.follow_symlinks = true;
+        let mut expected = vec![outside.join("lib"), root.join("repos/app")];
+        expected.sort();
+        assert_eq!(discover(&target), expected);
+
+        // A scan root that is itself a link to a repo.
+        let target = ScanTarget::new(root.join("app-link"), &Scan::default());
+        assert_eq!(discover(&target), vec![root.join("repos/app")]);
+    }
+
+    #[tokio::test]
+    async fn test_find_stale_repos() {
+        let (dir, root) = scan_root();
+        let (_other_dir, other) = scan_root();
+        let gitdb = open_db(&dir);
+        for path in ["repos/kept", "repos/gone", "repos/emptied"] {
+            init_repo(&root.join(path));
+        }
+        init_repo(&other.join("elsewhere"));
+        let repos = root.join("repos");
+        let scanned = check_and_save(&[&repos, &other], &History::default(), &gitdb).await;
+        assert_eq!(scanned, vec![repos.clone(), other.clone()]);
+
+        fs::remove_dir_all(repos.join("gone")).unwrap();
+        fs::remove_dir_all(repos.join("emptied/.git")).unwrap();
+        fs::remove_dir_all(other.join("elsewhere")).unwrap();
+        let path = |path: &Path| path.display().to_string();
+        let mut stale = find_stale_repos(&gitdb, std::slice::from_ref(&repos)).unwrap();
+        stale.sort();
+        assert_eq!(
+            stale,
+            vec![path(&repos.join("emptied")), path(&repos.join("gone"))]
+        );
+        assert_eq!(find_stale_repos(&gitdb, &[]).unwrap().len(), 3);
+
+        // Roots that fail to scan are not reported as scanned, so nothing below them is pruned.
+        let missing = root.join("unmounted");
+        let scanned = check_and_save(&[&missing, &repos], &History::default(), &gitdb).await;
+        assert_eq!(scanned, vec![repos.clone()]);
+
+        // A root whose last repo is gone still counts as scanned.
+        let scanned = check_and_save(&[&other], &History::default(), &gitdb).await;
+        assert_eq!(scanned, vec![other.clone()]);
+        assert_eq!(
+            find_stale_repos(&gitdb, &scanned).unwrap(),
+            vec![path(&other.join("elsewhere"))]
+        );
+
+        // Pruning keeps the snapshots around for `history`.
+        let gone = path(&repos.join("gone"));
+        gitdb.remove_repo(&gone).unwrap();
+        assert!(!gitdb.repo_paths().unwrap().contains(&gone));
+        assert_eq!(gitdb.get_snapshots(&gone).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_moved_repos() {
+        let (dir, root) = scan_root();
+        let gitdb = open_db(&dir);
+        let path = |path: &Path| path.display().to_string();
+        let repo = init_repo(&root.join("clone"));
+        repo.remote("origin", "https://example.com/app.git")
+            .unwrap();
+        check_and_save(&[&root.join("clone")], &History::default(), &gitdb).await;
+
+        fs::rename(root.join("clone"), root.join("moved")).unwrap();
+        check_and_save(&[&root.join("moved")], &History::default(), &gitdb).await;
+        assert_eq!(gitdb.repo_paths().unwrap(), vec![path(&root.join("moved"))]);
+        assert!(gitdb
+            .get_snapshots(&path(&root.join("clone")))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            gitdb
+                .get_snapshots(&path(&root.join("moved")))
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // A second clone that still exists next to the first is a repo of its own.
+        let copy = Repository::clone(&path(&root.join("moved")), root.join("copy")).unwrap();
+        copy.remote_set_url("origin", "https://example.com/app.git")
+            .unwrap();
+        check_and_save(&[&root.join("copy")], &History::default(), &gitdb).await;
+        assert_eq!(gitdb.repo_paths().unwrap().len(), 2);
+
+        assert!(!gitdb
+            .rename_repo(&path(&root.join("clone")), "/elsewhere")
+            .unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinked_records() {
+        let (dir, root) = scan_root();
+        let gitdb = open_db(&dir);
+        let path = |path: &Path| path.display().to_string();
+        init_repo(&root.join("app"));
+        std::os::unix::fs::symlink(root.join("app"), root.join("link")).unwrap();
+        check_and_save(&[&root.join("app")], &History::default(), &gitdb).await;
+        // As stored before keys were canonicalized.
+        assert!(gitdb
+            .rename_repo(&path(&root.join("app")), &path(&root.join("link")))
+            .unwrap());
+
+        check_and_save(&[&root.join("app")], &History::default(), &gitdb).await;
+        assert_eq!(gitdb.repo_paths().unwrap(), vec![path(&root.join("app"))]);
+        assert_eq!(
+            gitdb.get_snapshots(&path(&root.join("app"))).unwrap().len(),
+            2
+        );
+
+        // `db migrate` re-keys them without a check.
+        assert!(gitdb
+            .rename_repo(&path(&root.join("app")), &path(&root.join("link")))
+            .unwrap());
+        let report = gitdb.migrate(&dir.path().join("backup")).unwrap();
+        assert_eq!(report.rekeyed, 1);
+        assert_eq!(gitdb.repo_paths().unwrap(), vec![path(&root.join("app"))]);
+    }
+
+    fn checkout(repo: &Repository, branch: &str) {
+        repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+    }
+
+    /// Checks the repo and compares the stored history with one collected from scratch.
+    async fn reconciled_history(
+        repo: &Repository,
+        history: &History,
+        gitdb: &GitDatabase,
+    ) -> Vec<(String, Vec<String>)> {
+        let path = fs::canonicalize(repo.workdir().unwrap()).unwrap();
+        check_and_save(&[&path], history, gitdb).await;
+        let summarize = |commits: Vec<GitCommit>| {
+            let mut commits: Vec<(String, Vec<String>)> = commits
+                .into_iter()
+                .map(|mut commit| {
+                    commit.branches.sort();
+                    (commit.message, commit.branches)
+                })
+                .collect();
+            commits.sort();
+            commits
+        };
+        let stored = gitdb.get_repo_details(path).unwrap().commits.unwrap();
+        let (rebuilt, _) = get_commits_history(repo, &1, history, None).unwrap();
+        let stored = summarize(stored);
+        assert_eq!(stored, summarize(rebuilt));
+        stored
+    }
+
+    #[tokio::test]
+    async fn test_history_reconciliation() {
+        let (dir, root) = scan_root();
+        let gitdb = open_db(&dir);
+        let history = History {
+            refs: vec![HISTORY_LOCAL.to_string()],
+            ..History::default()
+        };
+        let repo = init_repo(&root.join("app"));
+        let initial = repo.head().unwrap().peel_to_commit().unwrap();
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        commit_file(&repo, "a.txt", "a\n", "Second");
+        let both = vec!["feat".to_string(), main.clone()];
+        let history_of = |commits: &[(&str, &[String])]| -> Vec<(String, Vec<String>)> {
+            commits
+                .iter()
+                .map(|(message, branches)| (message.to_string(), branches.to_vec()))
+                .collect()
+        };
+        reconciled_history(&repo, &history, &gitdb).await;
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("feat", &head, false).unwrap();
+        checkout(&repo, "feat");
+        commit_file(&repo, "feat.txt", "feat\n", "Feature");
+        checkout(&repo, &main);
+        commit_file(&repo, "main.txt", "main\n", "Main");
+        assert_eq!(
+            reconciled_history(&repo, &history, &gitdb).await,
+            history_of(&[
+                ("Feature", &both[..1]),
+                ("Initial commit", &both),
+                ("Main", &both[1..]),
+                ("Second", &both),
+            ])
+        );
+
+        // Commits only reachable from a deleted branch are dropped.
+        repo.find_branch("feat", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        assert_eq!(
+            reconciled_history(&repo, &history, &gitdb).await,
+            history_of(&[
+                ("Initial commit", &both[1..]),
+                ("Main", &both[1..]),
+                ("Second", &both[1..]),
+            ])
+        );
+
+        // And so are commits rewritten by a reset.
+        repo.reset(initial.as_object(), git2::ResetType::Hard, None)
+            .unwrap();
+        commit_file(&repo, "b.txt", "b\n", "Rewritten");
+        assert_eq!(
+            reconciled_history(&repo, &history, &gitdb).await,
+            history_of(&[("Initial commit", &both[1..]), ("Rewritten", &both[1..])])
+        );
+    }
+
+    /// Stages every change in the work tree and commits it on top of `parents`, or HEAD.
+    fn commit_all(repo: &Repository, message: &str, parents: &[&Commit]) -> Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Tester", "tester@example.com").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let parents = if parents.is_empty() {
+            vec![&head]
+        } else {
+            parents.to_vec()
+        };
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn find_commit(repo: &Repository, history: &History, message: &str) -> GitCommit {
+        let (commits, _) = get_commits_history(repo, &1, history, None).unwrap();
+        commits
+            .into_iter()
+            .find(|commit| commit.message == message)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_merge_stats() {
+        let (_dir, root) = scan_root();
+        let repo = init_repo(&root.join("app"));
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        commit_file(&repo, "a.txt", "base\n", "Base");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("feat", &head, false).unwrap();
+        checkout(&repo, "feat");
+        commit_file(&repo, "feat.txt", "1\n2\n3\n", "Feature");
+        let feat = commit_file(&repo, "a.txt", "feat\n", "Feature edit");
+        checkout(&repo, &main);
+        let ours = commit_file(&repo, "a.txt", "main\n", "Main edit");
+
+        // Resolve the conflict in a.txt with two lines of its own.
+        fs::write(workdir.join("feat.txt"), "1\n2\n3\n").unwrap();
+        fs::write(workdir.join("a.txt"), "merged\nextra\n").unwrap();
+        let ours = repo.find_commit(ours).unwrap();
+        let feat = repo.find_commit(feat).unwrap();
+        commit_all(&repo, "Merge", &[&ours, &feat]);
+
+        let merge_stats = |merges: MergeStats| {
+            let history = History {
+                merges,
+                ..History::default()
+            };
+            let commit = find_commit(&repo, &history, "Merge");
+            let mut files: Vec<String> = commit.files.into_iter().map(|file| file.path).collect();
+            files.sort();
+            (files, commit.insertions, commit.deletion)
+        };
+        assert_eq!(merge_stats(MergeStats::Exclude), (vec![], 0, 0));
+        // The feature branch's own lines are counted again on the merge.
+        assert_eq!(
+            merge_stats(MergeStats::FirstParent),
+            (vec!["a.txt".to_string(), "feat.txt".to_string()], 5, 1)
+        );
+        // Only the conflict resolution, taken against the first parent's side.
+        assert_eq!(
+            merge_stats(MergeStats::AutoMerge),
+            (vec!["a.txt".to_string()], 2, 1)
+        );
+    }
+
+    #[test]
+    fn test_file_changes() {
+        let (_dir, root) = scan_root();
+        let repo = init_repo(&root.join("app"));
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        let notes: String = (1..=10).map(|line| format!("line {}\n", line)).collect();
+        commit_file(&repo, "notes.txt", &notes, "Add notes");
+        let history = History::default();
+        let files = |message: &str| find_commit(&repo, &history, message).files;
+
+        fs::rename(workdir.join("notes.txt"), workdir.join("docs.txt")).unwrap();
+        commit_all(&repo, "Rename", &[]);
+        assert_eq!(
+            files("Rename"),
+            vec![FileChange {
+                path: "docs.txt".to_string(),
+                old_path: Some("notes.txt".to_string()),
+                kind: ChangeKind::Renamed,
+                binary: false,
+                insertions: 0,
+                deletions: 0,
+            }]
+        );
+
+        // Copies are only found from files modified in the same commit.
+        fs::write(workdir.join("copy.txt"), &notes).unwrap();
+        fs::write(workdir.join("docs.txt"), format!("{}line 11\n", notes)).unwrap();
+        commit_all(&repo, "Copy", &[]);
+        let copy = files("Copy")
+            .into_iter()
+            .find(|file| file.path == "copy.txt")
+            .unwrap();
+        assert_eq!(copy.kind, ChangeKind::Copied);
+        assert_eq!(copy.old_path.as_deref(), Some("docs.txt"));
+
+        // Binary files are listed, but kept out of the line counts.
+        fs::write(workdir.join("image.bin"), [0u8, 1, 2, 0, 255, 0]).unwrap();
+        fs::write(workdir.join("docs.txt"), format!("{}line 12\n", notes)).unwrap();
+        commit_all(&repo, "Binary", &[]);
+        let commit = find_commit(&repo, &history, "Binary");
+        let image = commit
+            .files
+            .iter()
+            .find(|file| file.path == "image.bin")
+            .unwrap();
+        assert!(image.binary);
+        assert_eq!((image.insertions, image.deletions), (0, 0));
+        assert_eq!((commit.insertions, commit.deletion), (1, 1));
+    }
+
     #[cfg(feature = "dev")]
     #[test]
     fn test_is_git_repo() {
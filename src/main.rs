@@ -2,13 +2,17 @@ mod config;
 mod git_database;
 mod git_status;
 
-use config::Config;
+use config::{expand_tilde, parse_duration, Config, FetchPolicy, MergeStats, Snapshots};
 use git2::Repository;
-use git_database::GitDatabase;
-use git_status::check_dir;
+use git_database::{
+    schema_layout, FetchStatus, GitDatabase, GitRepoInfo, StatusCategory, SCHEMA_VERSION,
+};
+use git_status::{check_dir, find_stale_repos, set_fetch_timeout, ScanTarget};
+use glob::Pattern;
 use log::debug;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -22,17 +26,80 @@ struct Cli {
 enum GitCommand {
     #[structopt(about = "Check the status of a git repository and save it to the database.")]
     Check {
+        /// Directory to scan; when omitted, all roots from the [scan] config section are scanned.
         #[structopt(parse(from_os_str))]
-        path: PathBuf,
+        path: Option<PathBuf>,
         #[structopt(short = "L", long, default_value = "0", validator = validate_detail_level)]
         detail_level: u8,
+        /// Do not fetch from remotes, only use what is already known locally.
+        #[structopt(long)]
+        no_fetch: bool,
+        /// Only fetch repos whose last fetch is older than this (e.g. 30m, 12h, 2d).
+        #[structopt(long, parse(try_from_str = parse_duration), conflicts_with = "no-fetch")]
+        fetch_older_than: Option<Duration>,
+        /// Give up on a repo's fetch after this long (e.g. 30s, 2m).
+        #[structopt(long, parse(try_from_str = parse_timeout))]
+        fetch_timeout: Option<Duration>,
+        /// Number of repos to check in parallel (defaults to general.jobs or the CPU count).
+        #[structopt(short = "j", long, validator = validate_jobs)]
+        jobs: Option<usize>,
+        /// How many directory levels below the scanned path to search for repos.
+        #[structopt(long)]
+        max_depth: Option<usize>,
+        /// Skip directories matching this glob during discovery (repeatable).
+        #[structopt(long, parse(try_from_str = parse_pattern))]
+        exclude: Vec<Pattern>,
+        /// Also descend into hidden directories.
+        #[structopt(long)]
+        include_hidden: bool,
+        /// Keep descending into repos to also find repos nested inside them.
+        #[structopt(long)]
+        nested: bool,
+        /// Descend into symlinked directories during discovery.
+        #[structopt(long)]
+        follow_symlinks: bool,
+        /// Remove stored repos below the scanned paths that no longer exist.
+        #[structopt(long)]
+        prune: bool,
     },
     #[structopt(about = "Load the status of all git repositories from the database.")]
     Status {
         #[structopt(parse(from_os_str))]
         path: Option<PathBuf>,
+        /// Only show repos with changes in these categories (staged, modified, untracked, conflicted, ignored).
+        #[structopt(short = "f", long = "filter")]
+        filter: Vec<StatusCategory>,
     },
     Commits,
+    #[structopt(
+        about = "Remove stored repos that no longer exist on disk and expire old snapshots."
+    )]
+    Prune {
+        /// Only list the repos that would be removed.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    #[structopt(about = "Print the stored snapshots of a repository over time.")]
+    History {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+    #[structopt(about = "Maintain the database.")]
+    Db(DbCommand),
+    #[structopt(
+        about = "Report authors, commit types per week and breaking changes since the last tag."
+    )]
+    Report {
+        /// Repo to report on; when omitted, all repos in the database are reported.
+        #[structopt(parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum DbCommand {
+    #[structopt(about = "Back up the database, then rewrite all records with the current schema.")]
+    Migrate,
 }
 
 fn validate_detail_level(level: String) -> Result<(), String> {
@@ -44,6 +111,24 @@ fn validate_detail_level(level: String) -> Result<(), String> {
     }
 }
 
+fn validate_jobs(jobs: String) -> Result<(), String> {
+    match jobs.parse::<usize>() {
+        Ok(val) if val >= 1 => Ok(()),
+        _ => Err(String::from("Jobs must be a number greater than 0")),
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        timeout if timeout.is_zero() => Err(String::from("Timeout must be greater than 0")),
+        timeout => Ok(timeout),
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, glob::PatternError> {
+    Pattern::new(&expand_tilde(pattern))
+}
+
 fn get_absolute_path(path: &Path) -> std::io::Result<PathBuf> {
     let absolute_path = fs::canonicalize(path)?;
     Ok(absolute_path)
@@ -55,7 +140,7 @@ async fn main() {
     let config_path: PathBuf = dirs::home_dir().unwrap();
     debug!("Home dir: {:?}", config_path);
     let config_path = config_path.join(".config/sinh-x/gitstatus/config.toml");
-    let config = if config_path.exists() {
+    let mut config = if config_path.exists() {
         // Load the config from the file
         let config = Config::new(config_path.as_path()).unwrap();
         config.validate().expect("Invalid config");
@@ -65,19 +150,84 @@ async fn main() {
         Config::default()
     };
 
-    let binding = config.general.database_path.unwrap();
+    let binding = config.general.database_path.clone().unwrap();
     let db_path = Path::new(&binding);
     let gitdb = GitDatabase::new(db_path).unwrap();
 
     let args = Cli::from_args();
 
     match args.command {
-        GitCommand::Check { path, detail_level } => {
-            let absolute_path = get_absolute_path(path.as_path());
-            match check_dir(&absolute_path.unwrap(), &detail_level, &gitdb).await {
-                Ok(repos) => {
+        GitCommand::Check {
+            path,
+            detail_level,
+            no_fetch,
+            fetch_older_than,
+            fetch_timeout,
+            jobs,
+            max_depth,
+            exclude,
+            include_hidden,
+            nested,
+            follow_symlinks,
+            prune,
+        } => {
+            if let Some(fetch_timeout) = fetch_timeout {
+                config.fetch.timeout = fetch_timeout;
+            }
+            if no_fetch {
+                config.fetch.policy = FetchPolicy::Never;
+                config.fetch.rules.clear();
+            } else if let Some(fetch_older_than) = fetch_older_than {
+                config.fetch.policy = FetchPolicy::Stale;
+                config.fetch.stale_after = fetch_older_than;
+                config.fetch.rules.clear();
+            }
+            let mut targets: Vec<ScanTarget> = match path {
+                Some(path) => vec![ScanTarget::new(
+                    get_absolute_path(path.as_path()).expect("Path failed"),
+                    &config.scan,
+                )],
+                None => config
+                    .scan
+                    .roots
+                    .iter()
+                    .map(|root| ScanTarget::from_root(root, &config.scan))
+                    .collect(),
+            };
+            for target in targets.iter_mut() {
+                if max_depth.is_some() {
+                    target.max_depth = max_depth;
+                }
+                target.exclude.extend(exclude.iter().cloned());
+                if include_hidden {
+                    target.skip_hidden = false;
+                }
+                if nested {
+                    target.nested = true;
+                }
+                if follow_symlinks {
+                    target.follow_symlinks = true;
+                }
+            }
+            if targets.is_empty() {
+                eprintln!("Check Command Failed: no path given and no [scan] roots configured");
+                return;
+            }
+            let jobs = jobs.unwrap_or_else(|| config.general.jobs());
+            set_fetch_timeout(config.fetch.timeout);
+            match check_dir(
+                &targets,
+                &detail_level,
+                &config.fetch,
+                &config.history,
+                jobs,
+                &gitdb,
+            )
+            .await
+            {
+                Ok((repos, scanned_roots)) => {
                     for repo in repos {
-                        debug!("Status:\n{}", repo.status);
+                        debug!("Status: {}", repo.status_summary());
                         debug!("Unpushed commits:\n{}", repo.unpushed_commits);
                         debug!("Updates from remote:\n{}", repo.remote_updates);
 
@@ -86,11 +236,17 @@ async fn main() {
                             Err(e) => eprintln!("Failed to save to database: {}", e),
                         }
                     }
+                    // After saving, so moved repos have taken over their old records first.
+                    // Roots that failed to scan, e.g. an unmounted drive, are left alone.
+                    if (prune || config.scan.prune) && !scanned_roots.is_empty() {
+                        prune_repos(&gitdb, &scanned_roots, false);
+                    }
+                    expire_snapshots(&gitdb, &config.snapshots);
                 }
                 Err(e) => eprintln!("Check Command Failed: {}", e),
             }
         }
-        GitCommand::Status { path } => {
+        GitCommand::Status { path, filter } => {
             match path {
                 Some(path) => {
                     // Handle the case where path is Some
@@ -100,12 +256,47 @@ async fn main() {
                         Ok(repo_info) => {
                             println!("Test {}", repo_info.path);
                             println!("Test {}", repo_info.app_version);
-                            for commit in &repo_info.commits.unwrap_or_default() {
+                            if let Some(ref parent) = repo_info.parent {
+                                println!("Kind: {} {}", repo_info.kind, parent);
+                            }
+                            match repo_info.bare {
+                                Some(ref bare) => println!("Status: n/a ({})", bare),
+                                None => println!("Status: {}", repo_info.status_summary()),
+                            }
+                            for entry in &repo_info.status {
+                                println!("  {}", entry);
+                            }
+                            if let Some(last_fetch) = repo_info.last_fetch {
+                                println!("Last fetch: {}", last_fetch);
+                            }
+                            println!("Remote: {}", repo_info.fetch_status);
+                            if let Some(ref default_branch) = repo_info.default_branch {
+                                println!("Default branch: {}", default_branch);
+                            }
+                            for branch in &repo_info.branches {
+                                println!("Branch: {}", branch);
+                            }
+                            for commit in repo_info.commits.iter().flatten() {
                                 println!("Commit: {}", commit);
+                                if let Some(time) = commit.time.to_datetime() {
+                                    println!("Date: {}", time);
+                                }
+                                if !commit.branches.is_empty() {
+                                    println!("Branches: {}", commit.branches.join(", "));
+                                }
                                 println!(
                                     "{} | {} | {} ",
                                     commit.file_changes, commit.insertions, commit.deletion,
                                 );
+                                for file in &commit.files {
+                                    println!("  {}", file);
+                                }
+                            }
+                            for churn in repo_info.file_churn().iter().take(10) {
+                                println!(
+                                    "Most changed: {} ({} commits, +{}/-{})",
+                                    churn.path, churn.commits, churn.insertions, churn.deletions
+                                );
                             }
                             if let Some(ref languages) = repo_info.languages {
                                 for (name, language) in languages {
@@ -124,22 +315,220 @@ async fn main() {
                 }
                 None => match gitdb.get_summary_stats() {
                     Ok(repos) => {
-                        for repo in repos {
+                        for repo in repos.iter().filter(|repo| {
+                            filter.is_empty()
+                                || filter
+                                    .iter()
+                                    .any(|category| repo.status.count(*category) > 0)
+                        }) {
+                            let branches: Vec<String> = repo
+                                .branches
+                                .iter()
+                                .filter(|branch| !branch.is_synced())
+                                .map(|branch| {
+                                    format!("{} +{}/-{}", branch.name, branch.ahead, branch.behind)
+                                })
+                                .collect();
+                            let fetch_problem = match repo.fetch_status {
+                                FetchStatus::Failed(_) | FetchStatus::TimedOut => {
+                                    format!(" | {}", repo.fetch_status)
+                                }
+                                _ => String::new(),
+                            };
+                            let path = match repo.parent {
+                                Some(ref parent) => {
+                                    format!("{} ({} {})", repo.path, repo.kind, parent)
+                                }
+                                None => repo.path.clone(),
+                            };
+                            let status = match repo.bare {
+                                Some(ref bare) => bare.to_string(),
+                                None => repo.status.to_string(),
+                            };
                             println!(
-                                "{} | {} | {} | {}",
-                                repo.path,
-                                repo.status_lines,
+                                "{} | {} | {} | {} | {}{}",
+                                path,
+                                status,
                                 repo.unpushed_commits_lines,
                                 repo.remote_updates_lines,
+                                branches.join(", "),
+                                fetch_problem,
                             );
                         }
                     }
                     Err(e) => eprintln!("Status Commnd - All Failed: {}", e),
                 },
             }
-            let _ = gitdb.summary_repos_table();
         }
         GitCommand::Commits => print_all_commits(".").expect("Failed to print commits"),
+        GitCommand::Prune { dry_run } => {
+            prune_repos(&gitdb, &[], dry_run);
+            if !dry_run {
+                expire_snapshots(&gitdb, &config.snapshots);
+            }
+        }
+        GitCommand::History { path } => {
+            // Snapshots outlive the repo, so the path does not have to exist anymore.
+            let path = get_absolute_path(path.as_path())
+                .or_else(|_| std::path::absolute(&path).map(|path| path.components().collect()))
+                .unwrap_or(path);
+            match gitdb.get_snapshots(&path.display().to_string()) {
+                Ok(snapshots) if snapshots.is_empty() => {
+                    eprintln!("No snapshots stored for {}", path.display())
+                }
+                Ok(snapshots) => {
+                    for snapshot in snapshots {
+                        let head = match snapshot.head {
+                            Some(ref head) => {
+                                format!("{} {}", head.name, &head.id[..head.id.len().min(7)])
+                            }
+                            None => "no commits".to_string(),
+                        };
+                        let languages: Vec<String> = snapshot
+                            .languages
+                            .iter()
+                            .map(|(name, code)| format!("{} {}", name, code))
+                            .collect();
+                        println!(
+                            "{} | {} | +{}/-{} | {} | {}",
+                            snapshot.time.format("%Y-%m-%d %H:%M"),
+                            snapshot.status,
+                            snapshot.ahead,
+                            snapshot.behind,
+                            head,
+                            languages.join(", "),
+                        );
+                    }
+                }
+                Err(e) => eprintln!("History Command Failed: {}", e),
+            }
+        }
+        GitCommand::Db(DbCommand::Migrate) => {
+            let backup_path = db_path.join("backups").join(format!(
+                "repo_db-{}",
+                chrono::Utc::now().format("%Y%m%d%H%M%S")
+            ));
+            match gitdb.migrate(&backup_path) {
+                Ok(report) => {
+                    println!("Backed up database to {}", backup_path.display());
+                    for (version, count) in &report.migrated {
+                        println!(
+                            "Migrated {} records from schema v{} ({} layout)",
+                            count,
+                            version,
+                            schema_layout(*version)
+                        );
+                    }
+                    println!(
+                        "{} records already at schema v{}",
+                        report.current, SCHEMA_VERSION
+                    );
+                    if report.rekeyed > 0 {
+                        println!(
+                            "Moved {} records from symlinked to canonical paths",
+                            report.rekeyed
+                        );
+                    }
+                    if report.skipped > 0 {
+                        println!("Skipped {} unreadable records", report.skipped);
+                    }
+                }
+                Err(e) => eprintln!("Migrate Command Failed: {}", e),
+            }
+        }
+        GitCommand::Report { path } => {
+            let repos = match path {
+                Some(path) => get_absolute_path(path.as_path())
+                    .map_err(|e| e.to_string())
+                    .and_then(|path| gitdb.get_repo_details(path).map_err(|e| e.to_string()))
+                    .map(|repo| vec![repo]),
+                None => gitdb.load_from_db().map_err(|e| e.to_string()),
+            };
+            match repos {
+                Ok(repos) => {
+                    let include_merges = config.history.merges != MergeStats::Exclude;
+                    for repo in repos {
+                        print_report(&repo, config.report.co_author_weight, include_merges);
+                    }
+                }
+                Err(e) => eprintln!("Report Command Failed: {}", e),
+            }
+        }
+    }
+}
+
+fn prune_repos(gitdb: &GitDatabase, roots: &[PathBuf], dry_run: bool) {
+    let stale = match find_stale_repos(gitdb, roots) {
+        Ok(stale) => stale,
+        Err(e) => {
+            eprintln!("Prune Failed: {}", e);
+            return;
+        }
+    };
+    for path in stale {
+        if dry_run {
+            println!("Would remove {}", path);
+            continue;
+        }
+        match gitdb.remove_repo(&path) {
+            Ok(()) => println!("Removed {}", path),
+            Err(e) => eprintln!("Failed to remove {}: {}", path, e),
+        }
+    }
+}
+
+fn expire_snapshots(gitdb: &GitDatabase, snapshots: &Snapshots) {
+    match gitdb.prune_all_snapshots(snapshots.max_age, snapshots.max_count) {
+        Ok(removed) => debug!("Expired {} snapshots", removed),
+        Err(e) => eprintln!("Failed to prune snapshots: {}", e),
+    }
+}
+
+fn print_report(repo: &GitRepoInfo, co_author_weight: f64, include_merges: bool) {
+    println!("{}", repo.path);
+    for author in repo.author_stats(co_author_weight, include_merges) {
+        println!(
+            "  {} <{}>: {} commits, +{}/-{}",
+            author.name,
+            author.email,
+            format_credit(author.commits),
+            format_credit(author.insertions),
+            format_credit(author.deletions),
+        );
+    }
+    for (week, kinds) in repo.commit_kinds_per_week() {
+        let kinds: Vec<String> = kinds
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        println!("  {}: {}", week, kinds.join(", "));
+    }
+    let breaking = repo.breaking_since_tag();
+    if !breaking.is_empty() {
+        match repo.latest_tag {
+            Some(ref tag) => println!("  Breaking changes since {}:", tag.name),
+            None => println!("  Breaking changes:"),
+        }
+        for commit in breaking {
+            let header = match commit.conventional {
+                Some(ref conventional) => conventional.to_string(),
+                None => commit.message.lines().next().unwrap_or("").to_string(),
+            };
+            println!(
+                "    {} {}",
+                &commit.hash[..commit.hash.len().min(7)],
+                header
+            );
+        }
+    }
+}
+
+/// Whole numbers without decimals, weighted shares with one.
+fn format_credit(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.1}", value)
     }
 }
 